@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::encoding::{Encode, Encoder};
+use crate::token_economy::NATIVE_ASSET;
+use crate::transaction::{Transaction, TxType};
+
+/// Объявление актива в IssueAsset-транзакции. Полномочия — адреса, которые
+/// вправе подписывать Mint и Burn этого актива; без полномочия операция
+/// для актива запрещена.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetDefinition {
+    pub symbol: String,
+    pub decimals: u8,
+    pub max_supply: u64,
+    #[serde(default)]
+    pub mint_authority: Option<String>,
+    #[serde(default)]
+    pub burn_authority: Option<String>,
+}
+
+impl Encode for AssetDefinition {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_str(&self.symbol)
+            .put_u8(self.decimals)
+            .put_u64(self.max_supply)
+            .put_option(&self.mint_authority)
+            .put_option(&self.burn_authority);
+    }
+}
+
+impl AssetDefinition {
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_symbol = !self.symbol.is_empty() && self.symbol.len() <= 12
+            && self.symbol.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        if !valid_symbol {
+            return Err(format!("Invalid asset symbol {:?}", self.symbol));
+        }
+        if self.symbol == NATIVE_ASSET {
+            return Err(format!("{} is the native asset and cannot be issued", NATIVE_ASSET));
+        }
+        if self.decimals > 18 || self.max_supply == 0 {
+            return Err(format!("Asset {} must have at most 18 decimals and a positive max supply", self.symbol));
+        }
+        Ok(())
+    }
+}
+
+/// Зарегистрированный актив и сколько его выпущено за всё время.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetInfo {
+    pub definition: AssetDefinition,
+    pub issuer: String,
+    pub minted: u64,
+}
+
+/// Реестр активов в состоянии цепочки. Нативный актив встроен: он
+/// выпускается только наградой блока и в реестр не записывается.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AssetRegistry {
+    assets: BTreeMap<String, AssetInfo>,
+}
+
+impl AssetRegistry {
+    pub fn get(&self, symbol: &str) -> Option<&AssetInfo> {
+        self.assets.get(symbol)
+    }
+
+    pub fn is_registered(&self, symbol: &str) -> bool {
+        symbol == NATIVE_ASSET || self.assets.contains_key(symbol)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AssetInfo)> {
+        self.assets.iter()
+    }
+
+    pub fn register(&mut self, definition: &AssetDefinition, issuer: &str) -> Result<(), String> {
+        definition.validate()?;
+        if self.assets.contains_key(&definition.symbol) {
+            return Err(format!("Asset {} is already registered", definition.symbol));
+        }
+        let info = AssetInfo { definition: definition.clone(), issuer: issuer.to_string(), minted: 0 };
+        self.assets.insert(definition.symbol.clone(), info);
+        Ok(())
+    }
+
+    /// Учитывает выпуск: не больше `max_supply` за всё время, даже после сжигания.
+    pub fn record_mint(&mut self, symbol: &str, amount: u64) -> Result<(), String> {
+        let info = self.assets.get_mut(symbol).ok_or(format!("Asset {} is not registered", symbol))?;
+        let minted = info.minted.checked_add(amount).filter(|m| *m <= info.definition.max_supply)
+            .ok_or(format!("Minting {} {} exceeds max supply {}", amount, symbol, info.definition.max_supply))?;
+        info.minted = minted;
+        Ok(())
+    }
+
+    /// Правила реестра для транзакции: все выходы ссылаются на
+    /// зарегистрированные активы, Mint и Burn подписаны полномочием актива,
+    /// IssueAsset выпускает только объявляемый актив.
+    pub fn check(&self, tx: &Transaction) -> Result<(), String> {
+        match tx.tx_type {
+            TxType::IssueAsset => {
+                let definition = tx.asset_definition.as_ref().ok_or("IssueAsset must declare the asset")?;
+                definition.validate()?;
+                if self.is_registered(&definition.symbol) {
+                    return Err(format!("Asset {} is already registered", definition.symbol));
+                }
+                if let Some(output) = tx.outputs.iter().find(|o| o.asset != definition.symbol) {
+                    return Err(format!("IssueAsset may only distribute {}, not {}", definition.symbol, output.asset));
+                }
+                return Ok(());
+            }
+            TxType::Mint | TxType::Burn => {
+                for output in &tx.outputs {
+                    let definition = &self.get(&output.asset).ok_or(format!("Asset {} is not registered", output.asset))?.definition;
+                    let (authority, action) = match tx.tx_type {
+                        TxType::Mint => (&definition.mint_authority, "mint"),
+                        _ => (&definition.burn_authority, "burn"),
+                    };
+                    if authority.as_deref() != Some(tx.sender.as_str()) {
+                        return Err(format!("{} is not allowed to {} {}", tx.sender, action, output.asset));
+                    }
+                    if matches!(tx.tx_type, TxType::Burn) && output.recipient != tx.sender {
+                        return Err("Burn outputs must name the burning authority".into());
+                    }
+                }
+            }
+            _ => {}
+        }
+        if tx.asset_definition.is_some() {
+            return Err("Only IssueAsset may declare an asset".into());
+        }
+        match tx.outputs.iter().find(|o| !self.is_registered(&o.asset)) {
+            Some(output) => Err(format!("Asset {} is not registered", output.asset)),
+            None => Ok(()),
+        }
+    }
+
+    /// Каноническое кодирование для корня состояния.
+    pub fn encode(&self, enc: &mut Encoder) {
+        enc.put_u64(self.assets.len() as u64);
+        for info in self.assets.values() {
+            enc.put(&info.definition).put_str(&info.issuer).put_u64(info.minted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TxOutput;
+
+    fn definition() -> AssetDefinition {
+        AssetDefinition {
+            symbol: "GOLD".into(),
+            decimals: 2,
+            max_supply: 100,
+            mint_authority: Some("mint".into()),
+            burn_authority: Some("burn".into()),
+        }
+    }
+
+    fn tx(sender: &str, tx_type: TxType, asset: &str, recipient: &str) -> Transaction {
+        let mut tx = Transaction::new(sender.into(), vec![TxOutput { asset: asset.into(), recipient: recipient.into(), amount: 10 }], 0);
+        tx.tx_type = tx_type;
+        tx
+    }
+
+    #[test]
+    fn test_registry_rules() {
+        let mut registry = AssetRegistry::default();
+        assert!(registry.check(&tx("alice", TxType::Transfer, "GOLD", "bob")).is_err(), "Unregistered assets are rejected");
+        assert!(registry.check(&tx("alice", TxType::Transfer, NATIVE_ASSET, "bob")).is_ok());
+
+        registry.register(&definition(), "alice").unwrap();
+        assert!(registry.register(&definition(), "bob").is_err(), "Symbols are unique");
+        assert!(registry.check(&tx("alice", TxType::Transfer, "GOLD", "bob")).is_ok());
+        assert!(registry.check(&tx("mint", TxType::Mint, "GOLD", "bob")).is_ok());
+        assert!(registry.check(&tx("alice", TxType::Mint, "GOLD", "bob")).is_err(), "Only the mint authority may mint");
+        assert!(registry.check(&tx("burn", TxType::Burn, "GOLD", "burn")).is_ok());
+        assert!(registry.check(&tx("burn", TxType::Burn, "GOLD", "bob")).is_err(), "Burn cannot take someone else's tokens");
+
+        registry.record_mint("GOLD", 60).unwrap();
+        assert!(registry.record_mint("GOLD", 41).is_err(), "Max supply is enforced");
+        assert_eq!(registry.get("GOLD").unwrap().minted, 60);
+    }
+}
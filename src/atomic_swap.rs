@@ -5,6 +5,9 @@ pub struct HTLC {
     pub recipient: String,
     pub amount: u64,
     pub hashlock: String,
+    /// Срок в секундах Unix. `current_time` при погашении и возврате следует
+    /// брать из median-time-past цепочки (`ChainStore::median_time_past`), а
+    /// не из часов узла: медиана не убывает и одинакова у всех узлов.
     pub timelock: u64,
     pub redeemed: bool,
 }
@@ -42,6 +45,7 @@ mod tests {
     
     #[test]
     fn test_htlc_usage() {
+        init_logger();
         let mut htlc = HTLC::new("Alice".into(), "Bob".into(), 100, "secret", 1_000_000);
         // Попытка выкупа с правильным preimage:
         assert!(htlc.redeem("secret", 500_000).is_ok());
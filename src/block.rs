@@ -1,98 +1,275 @@
 use serde::{Serialize, Deserialize};
-use crate::transaction::{Transaction, calculate_merkle_root};
+use crate::transaction::Transaction;
+use crate::token_economy::FeeSplit;
+use crate::merkle::{calculate_merkle_root, merkle_proof, MerkleProof};
+use crate::encoding::{tag, to_hex, Encoder};
+use crate::target::{meets_target, U256};
+use crate::wallet::generate_address;
 use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
-use sha2::{Sha256, Digest};
-use chrono::Utc;
-use rayon::prelude::*;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter};
 
-#[derive(Clone, Serialize, Deserialize, Default)]
-pub struct Block {
-    pub index: u64,
-    pub previous_hash: String,
+pub const BLOCK_VERSION: u32 = 1;
+
+/// Заголовок блока. Его хеш является идентификатором блока; заголовков
+/// достаточно для синхронизации цепочки и для лёгких клиентов.
+///
+/// Хеш блока фиксирует все поля заголовка в порядке объявления: version,
+/// height, parent_hash, merkle_root, state_root, timestamp, bits,
+/// producer, consensus_algorithm, miner_reward, transaction_fee, signature,
+/// producer_key, extra_nonce и последним — nonce. Транзакции фиксируются
+/// через merkle_root. Подпись производителя покрывает те же поля, кроме
+/// signature, producer_key, extra_nonce и nonce; последние два подбираются
+/// майнером уже после подписи.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub height: u64,
+    pub parent_hash: String,
+    pub merkle_root: String,
+    pub state_root: String,
     pub timestamp: u64,
-    pub transactions: Vec<Transaction>,
+    /// Цель PoW в компактном формате (см. `target::U256::from_compact`).
+    pub bits: u32,
+    pub producer: String,
     pub consensus_algorithm: String,
+    pub miner_reward: u64,
+    pub transaction_fee: u64,
     #[serde(with = "serde_bytes")]
     pub signature: Option<Vec<u8>>,
-    pub hash: Option<String>,
+    /// Открытый ключ производителя; его адрес должен совпадать с `producer`.
+    #[serde(default, with = "serde_bytes")]
+    pub producer_key: Option<Vec<u8>>,
+    #[serde(default)]
+    pub extra_nonce: u64,
     pub nonce: u64,
-    pub miner_reward: u64,
-    pub transaction_fee: u64,
+}
+
+impl BlockHeader {
+    /// Поля, которые подписывает производитель.
+    fn encode_signed_fields(&self, enc: &mut Encoder) {
+        enc.put_u32(self.version)
+            .put_u64(self.height)
+            .put_str(&self.parent_hash)
+            .put_str(&self.merkle_root)
+            .put_str(&self.state_root)
+            .put_u64(self.timestamp)
+            .put_u32(self.bits)
+            .put_str(&self.producer)
+            .put_str(&self.consensus_algorithm)
+            .put_u64(self.miner_reward)
+            .put_u64(self.transaction_fee);
+    }
+
+    /// Прообраз хеша заголовка без nonce; nonce дописывается последним полем.
+    pub(crate) fn hash_prefix(&self) -> Encoder {
+        let mut enc = Encoder::new(tag::BLOCK_HASH);
+        self.encode_signed_fields(&mut enc);
+        enc.put_option(&self.signature).put_option(&self.producer_key).put_u64(self.extra_nonce);
+        enc
+    }
+
+    pub(crate) fn hash_with_nonce(prefix: &Encoder, nonce: u64) -> [u8; 32] {
+        let mut enc = prefix.clone();
+        enc.put_u64(nonce);
+        enc.hash()
+    }
+
+    pub fn hash_bytes(&self) -> [u8; 32] {
+        Self::hash_with_nonce(&self.hash_prefix(), self.nonce)
+    }
+
+    pub fn hash(&self) -> String {
+        to_hex(&self.hash_bytes())
+    }
+
+    /// Пересчитанный хеш заголовка не превышает цель, заявленную в `bits`.
+    pub fn meets_target(&self) -> bool {
+        match U256::from_compact(self.bits) {
+            Some(target) => meets_target(&self.hash_bytes(), &target),
+            None => false,
+        }
+    }
+
+    /// Сообщение для подписи производителя.
+    fn signing_message(&self) -> Vec<u8> {
+        let mut enc = Encoder::new(tag::BLOCK_SIGNATURE);
+        self.encode_signed_fields(&mut enc);
+        enc.into_bytes()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct BlockBody {
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub body: BlockBody,
+    pub hash: Option<String>,
 }
 
 impl Block {
     pub fn new(index: u64, previous_hash: String, timestamp: u64, transactions: Vec<Transaction>, consensus_algorithm: String) -> Self {
         Block {
-            index,
-            previous_hash,
-            timestamp,
-            transactions,
-            consensus_algorithm,
-            signature: None,
+            header: BlockHeader {
+                version: BLOCK_VERSION,
+                height: index,
+                parent_hash: previous_hash,
+                merkle_root: calculate_merkle_root(&transactions),
+                timestamp,
+                consensus_algorithm,
+                miner_reward: 50,
+                transaction_fee: 5,
+                ..BlockHeader::default()
+            },
+            body: BlockBody { transactions },
             hash: None,
-            nonce: 0,
-            miner_reward: 50,
-            transaction_fee: 5,
         }
     }
-    
+
+    /// Комиссии транзакций блока (у coinbase комиссии нет). Сумма насыщается:
+    /// блок с переполняющими комиссиями всё равно отвергается состоянием.
+    pub fn fees(&self) -> u64 {
+        self.body.transactions.iter().fold(0u64, |total, tx| total.saturating_add(tx.fee))
+    }
+
+    /// Ставит первой транзакцией coinbase, выплачивающую производителю награду
+    /// из заголовка и все комиссии, и пересчитывает merkle_root.
+    pub fn insert_coinbase(&mut self, chain_id: u64) -> Result<(), String> {
+        self.insert_coinbase_with(chain_id, &FeeSplit::default())
+    }
+
+    /// То же, но комиссии распределяются по `fees`.
+    pub fn insert_coinbase_with(&mut self, chain_id: u64, fees: &FeeSplit) -> Result<(), String> {
+        let outputs = fees.coinbase_outputs(&self.header.producer, self.header.miner_reward, self.fees())?;
+        let coinbase = Transaction::coinbase(chain_id, self.header.height, self.header.timestamp, outputs);
+        self.body.transactions.insert(0, coinbase);
+        self.header.merkle_root = self.calculate_merkle_root();
+        Ok(())
+    }
+
     pub fn calculate_merkle_root(&self) -> String {
-        calculate_merkle_root(&self.transactions)
+        calculate_merkle_root(&self.body.transactions)
+    }
+
+    /// Сверяет записанный в заголовке корень с корнем, пересчитанным из транзакций.
+    pub fn verify_merkle_root(&self) -> bool {
+        self.header.merkle_root == self.calculate_merkle_root()
+    }
+
+    pub fn merkle_proof(&self, tx_id: &str) -> Option<MerkleProof> {
+        merkle_proof(&self.body.transactions, tx_id)
     }
-    
+
     pub fn sign(&mut self, keypair: &Keypair) {
-        let transactions_str = serde_json::to_string(&self.transactions).unwrap_or_default();
-        let message = format!("{}{}{}{}", self.index, self.previous_hash, self.timestamp, transactions_str);
-        let signature = keypair.sign(message.as_bytes());
-        self.signature = Some(signature.to_bytes().to_vec());
+        let signature = keypair.sign(&self.header.signing_message());
+        self.header.signature = Some(signature.to_bytes().to_vec());
+        self.header.producer_key = Some(keypair.public.to_bytes().to_vec());
+    }
+
+    /// Блок подписан ключом, из которого выводится адрес производителя.
+    pub fn verify_producer(&self) -> bool {
+        match self.header.producer_key.as_deref().map(PublicKey::from_bytes) {
+            Some(Ok(public_key)) => generate_address(&public_key) == self.header.producer && self.verify_signature(&public_key),
+            _ => false,
+        }
     }
-    
+
     pub fn verify_signature(&self, public_key: &PublicKey) -> bool {
-        if let Some(sig_bytes) = &self.signature {
+        if let Some(sig_bytes) = &self.header.signature {
             if let Ok(signature) = Signature::from_bytes(sig_bytes) {
-                let transactions_str = serde_json::to_string(&self.transactions).unwrap_or_default();
-                let message = format!("{}{}{}{}", self.index, self.previous_hash, self.timestamp, transactions_str);
-                return public_key.verify(message.as_bytes(), &signature).is_ok();
+                return public_key.verify(&self.header.signing_message(), &signature).is_ok();
             }
         }
         false
     }
-    
-    pub fn mine(&mut self, difficulty: u64, last_block_time: i64) {
-        let target = "0".repeat(difficulty as usize);
-        let elapsed = Utc::now().timestamp() - last_block_time;
-        let adjusted = if elapsed < 60 { difficulty.saturating_add(1) } else if elapsed > 120 { difficulty.saturating_sub(1) } else { difficulty };
-        let merkle_root = self.calculate_merkle_root();
-        self.nonce = (0..=u64::MAX).into_par_iter().find_first(|&nonce| {
-            let mut hasher = Sha256::new();
-            let input = format!("{}{}{}{}{}", self.index, self.previous_hash, self.timestamp, merkle_root, nonce);
-            hasher.update(input);
-            let hash = format!("{:x}", hasher.finalize());
-            hash.starts_with(&target)
-        }).unwrap_or(0);
-        let mut hasher = Sha256::new();
-        let input = format!("{}{}{}{}{}", self.index, self.previous_hash, self.timestamp, merkle_root, self.nonce);
-        hasher.update(input);
-        self.hash = Some(format!("{:x}", hasher.finalize()));
-    }
-    
+
+    pub fn calculate_hash(&self) -> String {
+        self.header.hash()
+    }
+
+    /// Пересчитывает хеш из содержимого блока вместо доверия к сохранённому полю `hash`.
+    pub fn verify_hash(&self) -> bool {
+        self.hash.as_deref() == Some(self.header.hash().as_str())
+    }
+
     pub fn is_unique_hash(blockchain: &[Block], hash: &str) -> bool {
         !blockchain.iter().any(|b| b.hash.as_deref() == Some(hash))
     }
-    
+
+    pub fn headers(blocks: &[Block]) -> Vec<BlockHeader> {
+        blocks.iter().map(|b| b.header.clone()).collect()
+    }
+
     pub fn save_to_file(blocks: &[Block], path: &str) -> io::Result<()> {
         let file = File::create(path)?;
         let writer = BufWriter::new(file);
         serde_json::to_writer(writer, blocks)?;
         Ok(())
     }
-    
+
     pub fn load_from_file(path: &str) -> io::Result<Vec<Block>> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         let blocks = serde_json::from_reader(reader)?;
         Ok(blocks)
     }
+
+    /// Сохраняет только заголовки — для синхронизации и лёгких клиентов.
+    pub fn save_headers_to_file(blocks: &[Block], path: &str) -> io::Result<()> {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer(writer, &Self::headers(blocks))?;
+        Ok(())
+    }
+
+    pub fn load_headers_from_file(path: &str) -> io::Result<Vec<BlockHeader>> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let headers = serde_json::from_reader(reader)?;
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::POW_LIMIT_BITS;
+    use crate::miner::{CancellationToken, MineResult, Miner};
+    use rand::rngs::OsRng;
+
+    fn mined_block() -> Block {
+        let mut block = Block::new(1, "0".into(), 1675303065, Vec::new(), "Hybrid".into());
+        block.header.bits = POW_LIMIT_BITS;
+        block.sign(&Keypair::generate(&mut OsRng));
+        match Miner::default().mine(block, &CancellationToken::new(), |_| {}) {
+            MineResult::Found { block, .. } => *block,
+            _ => panic!("Block should be mined at the pow limit"),
+        }
+    }
+
+    #[test]
+    fn test_hash_commits_to_reward_fee_and_algorithm() {
+        let block = mined_block();
+        assert!(block.verify_hash());
+
+        let mut tampered = block.clone();
+        tampered.header.miner_reward += 1;
+        assert!(!tampered.verify_hash(), "Reward must be covered by the hash");
+
+        let mut tampered = block.clone();
+        tampered.header.transaction_fee = 0;
+        assert!(!tampered.verify_hash(), "Fee must be covered by the hash");
+
+        let mut tampered = block.clone();
+        tampered.header.consensus_algorithm = "PoS".into();
+        assert!(!tampered.verify_hash(), "Consensus algorithm must be covered by the hash");
+
+        let mut tampered = block;
+        tampered.header.signature = None;
+        assert!(!tampered.verify_hash(), "Signature must be covered by the hash");
+    }
 }
@@ -0,0 +1,148 @@
+use serde::{Serialize, Deserialize};
+use log::warn;
+use crate::block::Block;
+use crate::consensus_plugin::{ChainContext, ConsensusPlugin};
+use crate::transaction::Transaction;
+
+/// Газ транзакции без явного `gas_limit` (обычный перевод).
+pub const TRANSFER_GAS: u64 = 21_000;
+/// Запас на заголовок блока при наборе транзакций: их суммарный размер
+/// не должен занимать весь блок.
+pub const HEADER_RESERVE: usize = 2_048;
+
+/// Ограничения блока, входящие в правила консенсуса. Размер — длина блока
+/// в JSON, в котором он хранится и передаётся.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockLimits {
+    pub max_block_size: usize,
+    pub max_block_gas: u64,
+    pub max_transactions: usize,
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        BlockLimits {
+            max_block_size: 1_000_000,
+            max_block_gas: 10_000_000,
+            max_transactions: 1_000,
+        }
+    }
+}
+
+/// Газ coinbase не учитывается: она есть в каждом блоке. Меньше газа
+/// перевода не занимает ни одна транзакция, какой бы `gas_limit` ни указал отправитель.
+pub fn tx_gas(tx: &Transaction) -> u64 {
+    if tx.is_coinbase() {
+        return 0;
+    }
+    tx.gas_limit.unwrap_or(TRANSFER_GAS).max(TRANSFER_GAS)
+}
+
+fn serialized_size<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map(|bytes| bytes.len()).unwrap_or(usize::MAX)
+}
+
+impl BlockLimits {
+    pub fn check(&self, block: &Block) -> Result<(), String> {
+        let transactions = &block.body.transactions;
+        if transactions.len() > self.max_transactions {
+            return Err(format!("{} transactions exceed the limit of {}", transactions.len(), self.max_transactions));
+        }
+        let gas = transactions.iter().fold(0u64, |acc, tx| acc.saturating_add(tx_gas(tx)));
+        if gas > self.max_block_gas {
+            return Err(format!("Block gas {} exceeds the limit of {}", gas, self.max_block_gas));
+        }
+        let size = serialized_size(block);
+        if size > self.max_block_size {
+            return Err(format!("Block size {} exceeds the limit of {} bytes", size, self.max_block_size));
+        }
+        Ok(())
+    }
+
+    /// Набирает транзакции для блока в порядке очереди, пока они помещаются в
+    /// лимиты; одно место остаётся под coinbase. Возвращает (выбранные,
+    /// оставшиеся); транзакции, которые не поместятся ни в один блок, отбрасываются.
+    pub fn select(&self, candidates: Vec<Transaction>) -> (Vec<Transaction>, Vec<Transaction>) {
+        let max_size = self.max_block_size.saturating_sub(HEADER_RESERVE);
+        let mut selected = Vec::new();
+        let mut remaining = Vec::new();
+        let (mut gas, mut size) = (0u64, 0usize);
+        for tx in candidates {
+            let (tx_gas, tx_size) = (tx_gas(&tx), serialized_size(&tx) + 1);
+            if tx_gas > self.max_block_gas || tx_size > max_size {
+                warn!("Transaction {} can never fit into a block, dropping it", tx.id);
+                continue;
+            }
+            let fits = remaining.is_empty()
+                && selected.len() + 1 < self.max_transactions
+                && gas + tx_gas <= self.max_block_gas
+                && size + tx_size <= max_size;
+            if fits {
+                gas += tx_gas;
+                size += tx_size;
+                selected.push(tx);
+            } else {
+                remaining.push(tx);
+            }
+        }
+        (selected, remaining)
+    }
+}
+
+impl ConsensusPlugin for BlockLimits {
+    fn name(&self) -> &'static str {
+        "BlockLimits"
+    }
+
+    fn validate(&self, block: &Block, _ctx: &ChainContext) -> bool {
+        match self.check(block) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Block {} violates block limits: {}", block.header.height, e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TxOutput;
+
+    fn transfer(amount: u64) -> Transaction {
+        let output = TxOutput { asset: "TRD".into(), recipient: "bob".into(), amount };
+        Transaction::new("alice".into(), vec![output], 1)
+    }
+
+    #[test]
+    fn test_builder_output_passes_validation() {
+        let limits = BlockLimits { max_transactions: 3, max_block_gas: 5 * TRANSFER_GAS, ..BlockLimits::default() };
+        let (selected, remaining) = limits.select((1..=5).map(transfer).collect());
+        assert_eq!((selected.len(), remaining.len()), (2, 3));
+
+        let mut block = Block::new(1, "0".into(), 0, selected, "PoW".into());
+        block.insert_coinbase(1).unwrap();
+        assert!(limits.check(&block).is_ok());
+        let block = Block::new(1, "0".into(), 0, remaining.into_iter().chain(Some(transfer(6))).collect(), "PoW".into());
+        assert!(limits.check(&block).is_err(), "Four transactions exceed the count limit");
+    }
+
+    #[test]
+    fn test_gas_and_size_limits() {
+        let limits = BlockLimits { max_block_gas: 2 * TRANSFER_GAS, ..BlockLimits::default() };
+        let mut heavy = transfer(1);
+        heavy.gas_limit = Some(3 * TRANSFER_GAS);
+        let (selected, remaining) = limits.select(vec![transfer(1), heavy, transfer(2), transfer(3)]);
+        assert_eq!(selected.len(), 2, "Over-limit transaction is dropped, the rest fill the gas budget");
+        assert_eq!(remaining.len(), 1);
+
+        let mut free = transfer(4);
+        free.gas_limit = Some(0);
+        assert_eq!(tx_gas(&free), TRANSFER_GAS, "A zero gas limit must not bypass the block gas cap");
+
+        let tiny = BlockLimits { max_block_size: 200, ..BlockLimits::default() };
+        assert!(tiny.check(&Block::new(1, "0".into(), 0, vec![transfer(1)], "PoW".into())).is_err());
+    }
+}
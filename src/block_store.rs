@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::block::Block;
+use crate::transaction::Transaction;
+
+const SEGMENT_FILE: &str = "blocks.dat";
+const RECORD_MAGIC: u32 = 0x5452_4442; // "TRDB"
+const RECORD_HEADER_LEN: u64 = 12;
+/// Верхняя граница длины записи: заголовок с большей длиной — порча, а не блок.
+const MAX_RECORD_LEN: u64 = 64 << 20;
+
+/// Где лежит транзакция: блок и её позиция в теле блока.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TxLocation {
+    pub block_hash: String,
+    pub height: u64,
+    pub position: u32,
+}
+
+/// Хранилище блоков: один append-only сегмент и индексы в памяти
+/// (по высоте, по хешу и по id транзакции).
+///
+/// Запись сегмента: magic (u32) | длина (u32) | контрольная сумма (u32,
+/// первые 4 байта SHA-256 содержимого) | блок в JSON. Индексы строятся при
+/// открытии одним проходом по сегменту; сами блоки читаются с диска по запросу.
+/// Неполная или повреждённая последняя запись (обрыв при записи) отрезается;
+/// порча в середине сегмента — ошибка открытия.
+pub struct BlockStore {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    by_height: Vec<u64>,
+    by_hash: HashMap<String, u64>,
+    tx_index: HashMap<String, TxLocation>,
+}
+
+fn checksum(payload: &[u8]) -> u32 {
+    let digest = Sha256::digest(payload);
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+enum Record {
+    Payload(Vec<u8>),
+    /// Сегмент закончился ровно на границе записи.
+    End,
+    /// Последняя запись сегмента оборвана или не сходится по контрольной сумме.
+    Torn,
+}
+
+/// Читает запись, до конца сегмента от которой осталось `remaining` байт.
+/// Длина из заголовка сверяется с остатком до выделения буфера.
+fn read_record<R: Read>(reader: &mut R, remaining: u64) -> io::Result<Record> {
+    if remaining == 0 {
+        return Ok(Record::End);
+    }
+    if remaining < RECORD_HEADER_LEN {
+        return Ok(Record::Torn);
+    }
+    let mut header = [0u8; RECORD_HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    let magic = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let len = u32::from_be_bytes(header[4..8].try_into().unwrap()) as u64;
+    let sum = u32::from_be_bytes(header[8..12].try_into().unwrap());
+    if magic != RECORD_MAGIC || len > MAX_RECORD_LEN {
+        return Err(invalid_data("corrupted block record header"));
+    }
+    let record_len = RECORD_HEADER_LEN + len;
+    if record_len > remaining {
+        return Ok(Record::Torn);
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    if checksum(&payload) != sum {
+        return if record_len == remaining { Ok(Record::Torn) } else { Err(invalid_data("block record checksum mismatch")) };
+    }
+    Ok(Record::Payload(payload))
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl BlockStore {
+    /// Открывает (или создаёт) хранилище в каталоге `dir` и восстанавливает индексы.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<BlockStore> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join(SEGMENT_FILE);
+        let file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        let mut store = BlockStore {
+            path,
+            file,
+            len: 0,
+            by_height: Vec::new(),
+            by_hash: HashMap::new(),
+            tx_index: HashMap::new(),
+        };
+        store.recover()?;
+        Ok(store)
+    }
+
+    fn recover(&mut self) -> io::Result<()> {
+        let file_len = self.file.metadata()?.len();
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut offset = 0u64;
+        while let Record::Payload(payload) = read_record(&mut reader, file_len - offset)
+            .map_err(|e| invalid_data(format!("Block store {} is corrupted at offset {}: {}", self.path.display(), offset, e)))? {
+            let block: Block = serde_json::from_slice(&payload).map_err(invalid_data)?;
+            if block.header.height != self.by_height.len() as u64 {
+                return Err(invalid_data(format!("Block at offset {} has height {}, expected {}", offset, block.header.height, self.by_height.len())));
+            }
+            self.index(&block, offset);
+            offset += RECORD_HEADER_LEN + payload.len() as u64;
+        }
+        if offset < file_len {
+            log::warn!("Block store {}: truncating {} bytes of incomplete data", self.path.display(), file_len - offset);
+            self.file.set_len(offset)?;
+            self.file.sync_all()?;
+        }
+        self.len = offset;
+        Ok(())
+    }
+
+    fn index(&mut self, block: &Block, offset: u64) {
+        let hash = block.calculate_hash();
+        for (position, tx) in block.body.transactions.iter().enumerate() {
+            self.tx_index.insert(tx.id.clone(), TxLocation {
+                block_hash: hash.clone(),
+                height: block.header.height,
+                position: position as u32,
+            });
+        }
+        self.by_height.push(offset);
+        self.by_hash.insert(hash, offset);
+    }
+
+    /// Дописывает следующий по высоте блок и дожидается сброса на диск.
+    pub fn append(&mut self, block: &Block) -> io::Result<()> {
+        let expected = self.by_height.len() as u64;
+        if block.header.height != expected {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Block {} appended out of order, expected height {}", block.header.height, expected)));
+        }
+        let payload = serde_json::to_vec(block).map_err(invalid_data)?;
+        // Запись длиннее предела не прочиталась бы при следующем открытии
+        if payload.len() as u64 > MAX_RECORD_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Block {} takes {} bytes, above the record limit {}", block.header.height, payload.len(), MAX_RECORD_LEN)));
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN as usize + payload.len());
+        record.extend_from_slice(&RECORD_MAGIC.to_be_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        record.extend_from_slice(&checksum(&payload).to_be_bytes());
+        record.extend_from_slice(&payload);
+        if let Err(e) = self.file.write_all(&record).and_then(|()| self.file.sync_data()) {
+            // Частично записанная запись отрезается, чтобы сегмент совпадал с индексами
+            let _ = self.file.set_len(self.len);
+            return Err(e);
+        }
+        let offset = self.len;
+        self.len += record.len() as u64;
+        self.index(block, offset);
+        Ok(())
+    }
+
+    /// Отрезает все блоки выше `height` (для реорганизации цепочки) и
+    /// возвращает их по порядку высоты.
+    pub fn truncate(&mut self, height: u64) -> io::Result<Vec<Block>> {
+        let keep = (height as usize + 1).min(self.by_height.len());
+        let mut removed = Vec::with_capacity(self.by_height.len() - keep);
+        for &offset in &self.by_height[keep..] {
+            removed.push(self.read_at(offset)?);
+        }
+        if let Some(&offset) = self.by_height.get(keep) {
+            self.file.set_len(offset)?;
+            self.file.sync_all()?;
+            self.len = offset;
+        }
+        self.by_height.truncate(keep);
+        for block in &removed {
+            self.by_hash.remove(&block.calculate_hash());
+            for tx in &block.body.transactions {
+                self.tx_index.remove(&tx.id);
+            }
+        }
+        Ok(removed)
+    }
+
+    fn read_at(&self, offset: u64) -> io::Result<Block> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        match read_record(&mut file, self.len.saturating_sub(offset))? {
+            Record::Payload(payload) => serde_json::from_slice(&payload).map_err(invalid_data),
+            _ => Err(invalid_data("corrupted block record")),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_height.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_height.is_empty()
+    }
+
+    /// Высота последнего сохранённого блока.
+    pub fn tip_height(&self) -> Option<u64> {
+        self.by_height.len().checked_sub(1).map(|h| h as u64)
+    }
+
+    pub fn get_by_height(&self, height: u64) -> io::Result<Option<Block>> {
+        match self.by_height.get(height as usize) {
+            Some(&offset) => self.read_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_by_hash(&self, hash: &str) -> io::Result<Option<Block>> {
+        match self.by_hash.get(hash) {
+            Some(&offset) => self.read_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn locate_transaction(&self, tx_id: &str) -> Option<&TxLocation> {
+        self.tx_index.get(tx_id)
+    }
+
+    pub fn get_transaction(&self, tx_id: &str) -> io::Result<Option<Transaction>> {
+        let location = match self.locate_transaction(tx_id) {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let block = self.get_by_height(location.height)?;
+        Ok(block.and_then(|b| b.body.transactions.into_iter().nth(location.position as usize)))
+    }
+
+    /// Читает все блоки по порядку высоты.
+    pub fn load_all(&self) -> io::Result<Vec<Block>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut blocks = Vec::with_capacity(self.len());
+        let mut offset = 0u64;
+        while blocks.len() < self.len() {
+            let payload = match read_record(&mut reader, self.len - offset)? {
+                Record::Payload(payload) => payload,
+                _ => return Err(invalid_data("corrupted block record")),
+            };
+            offset += RECORD_HEADER_LEN + payload.len() as u64;
+            blocks.push(serde_json::from_slice(&payload).map_err(invalid_data)?);
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TxOutput;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("triad-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn block(height: u64) -> Block {
+        let output = TxOutput { asset: "TRD".into(), recipient: format!("r{}", height), amount: 1 };
+        let tx = Transaction::new("sender".into(), vec![output], 0);
+        Block::new(height, format!("p{}", height), height, vec![tx], "Hybrid".into())
+    }
+
+    #[test]
+    fn test_append_reopen_and_lookup() {
+        let dir = temp_dir("reopen");
+        let blocks: Vec<Block> = (0..3).map(block).collect();
+        {
+            let mut store = BlockStore::open(&dir).unwrap();
+            for b in &blocks {
+                store.append(b).unwrap();
+            }
+            assert!(store.append(&block(7)).is_err());
+            let mut oversized = block(3);
+            oversized.body.transactions[0].payload = Some("x".repeat(MAX_RECORD_LEN as usize));
+            assert!(store.append(&oversized).is_err(), "Records above the limit must not be written");
+        }
+        let store = BlockStore::open(&dir).unwrap();
+        assert_eq!(store.tip_height(), Some(2));
+        let hash = blocks[1].calculate_hash();
+        assert_eq!(store.get_by_hash(&hash).unwrap().unwrap().header.height, 1);
+        assert_eq!(store.get_by_height(2).unwrap().unwrap().calculate_hash(), blocks[2].calculate_hash());
+        let tx_id = &blocks[2].body.transactions[0].id;
+        assert_eq!(store.get_transaction(tx_id).unwrap().unwrap().id, *tx_id);
+        assert_eq!(store.load_all().unwrap().len(), 3);
+
+        let mut store = store;
+        let removed = store.truncate(0).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(store.get_transaction(tx_id).unwrap().is_none());
+        store.append(&blocks[1]).unwrap();
+        drop(store);
+        assert_eq!(BlockStore::open(&dir).unwrap().tip_height(), Some(1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_partial_write_is_truncated() {
+        let dir = temp_dir("truncate");
+        {
+            let mut store = BlockStore::open(&dir).unwrap();
+            store.append(&block(0)).unwrap();
+            store.append(&block(1)).unwrap();
+        }
+        let path = dir.join(SEGMENT_FILE);
+        let full_len = fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(full_len - 10).unwrap();
+
+        let mut store = BlockStore::open(&dir).unwrap();
+        assert_eq!(store.tip_height(), Some(0), "Torn record must be dropped");
+        store.append(&block(1)).unwrap();
+        drop(store);
+        assert_eq!(BlockStore::open(&dir).unwrap().len(), 2);
+
+        // Порча не последней записи не отрезается молча
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[RECORD_HEADER_LEN as usize + 1] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        assert!(BlockStore::open(&dir).is_err(), "Mid-file corruption must be an error");
+        assert_eq!(fs::metadata(&path).unwrap().len(), bytes.len() as u64);
+
+        bytes[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert!(BlockStore::open(&dir).is_err(), "Oversized lengths must be rejected before allocating");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use crate::block::{Block, BlockHeader};
+use crate::chain_store::ChainStore;
+use crate::consensus_plugin::PluginManager;
+use crate::fork_choice::ForkChoice;
+use crate::mempool::Mempool;
+use crate::target::U256;
+use crate::verification::check_block;
+
+/// Чем закончилось добавление блока в дерево.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// Блок продолжил лучшую ветку.
+    Extended,
+    /// Блок лёг на боковую ветку, которая пока не тяжелее лучшей.
+    SideChain,
+    /// Боковая ветка стала лучшей: `disconnected` блоков откачено,
+    /// `connected` применено.
+    Reorganized { disconnected: usize, connected: usize },
+}
+
+struct Node {
+    header: BlockHeader,
+    /// Суммарный вес ветки от генезиса до этого блока.
+    weight: U256,
+    /// Тело хранится только для блоков вне лучшей ветки; блоки лучшей ветки
+    /// лежат в `ChainStore`.
+    block: Option<Block>,
+}
+
+/// Дерево всех известных блоков, включая боковые ветки. Лучшая ветка
+/// определяется правилом `ForkChoice` и всегда совпадает с цепочкой в
+/// `ChainStore`: при смене лучшей ветки хранилище откатывается до точки
+/// ветвления, применяются блоки новой ветки, а транзакции отключённых блоков
+/// возвращаются в mempool.
+pub struct BlockTree {
+    nodes: HashMap<String, Node>,
+    best: Option<String>,
+    fork_choice: Box<dyn ForkChoice>,
+    plugins: PluginManager,
+}
+
+impl BlockTree {
+    pub fn new(fork_choice: Box<dyn ForkChoice>) -> Self {
+        BlockTree { nodes: HashMap::new(), best: None, fork_choice, plugins: PluginManager::new() }
+    }
+
+    /// Плагины консенсуса, которые блок проходит до того, как получит вес:
+    /// для PoW это проверка `bits` по истории ветки и хеша по цели.
+    pub fn with_plugins(mut self, plugins: PluginManager) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Строит дерево по цепочке, уже лежащей в хранилище.
+    pub fn from_chain(chain: &dyn ChainStore, fork_choice: Box<dyn ForkChoice>) -> Self {
+        let mut tree = BlockTree::new(fork_choice);
+        for header in chain.headers() {
+            let hash = header.hash();
+            let weight = tree.parent_weight(header).unwrap_or(U256::ZERO)
+                .saturating_add(tree.fork_choice.weight(header));
+            tree.nodes.insert(hash.clone(), Node { header: header.clone(), weight, block: None });
+            tree.best = Some(hash);
+        }
+        tree
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn best_hash(&self) -> Option<&str> {
+        self.best.as_deref()
+    }
+
+    pub fn weight(&self, hash: &str) -> Option<U256> {
+        self.nodes.get(hash).map(|node| node.weight)
+    }
+
+    /// Заголовки от генезиса до блока `hash` включительно — контекст для
+    /// проверки его потомка на любой ветке.
+    pub fn ancestors(&self, hash: &str) -> Vec<BlockHeader> {
+        let mut headers = Vec::new();
+        let mut cursor = self.nodes.get(hash);
+        while let Some(node) = cursor {
+            headers.push(node.header.clone());
+            cursor = if node.header.height == 0 { None } else { self.nodes.get(&node.header.parent_hash) };
+        }
+        headers.reverse();
+        headers
+    }
+
+    fn parent_weight(&self, header: &BlockHeader) -> Option<U256> {
+        self.nodes.get(&header.parent_hash).map(|parent| parent.weight)
+    }
+
+    /// Добавляет блок и при необходимости переключает цепочку на его ветку.
+    /// Блок сначала проходит `check_block` относительно предков на своей
+    /// ветке — иначе непроверенный `bits` дал бы ему любой вес. Блок, который
+    /// не применяется к состоянию, удаляется из дерева вместе с потомками.
+    pub fn insert(&mut self, block: Block, chain: &mut dyn ChainStore, mempool: &mut Mempool) -> Result<BlockStatus, String> {
+        let hash = block.calculate_hash();
+        let height = block.header.height;
+        if !block.verify_hash() {
+            return Err(format!("Block {} hash does not match its contents", height));
+        }
+        if self.contains(&hash) {
+            return Err(format!("Block {} ({}) is already known", height, hash));
+        }
+        let parent_weight = if height == 0 {
+            if !self.is_empty() {
+                return Err("Genesis block is already known".into());
+            }
+            U256::ZERO
+        } else {
+            let parent = self.nodes.get(&block.header.parent_hash)
+                .ok_or_else(|| format!("Block {} has unknown parent {}", height, block.header.parent_hash))?;
+            if parent.header.height + 1 != height {
+                return Err(format!("Block {} does not follow its parent at height {}", height, parent.header.height));
+            }
+            check_block(&block, &self.ancestors(&block.header.parent_hash), &self.plugins)
+                .map_err(|kind| format!("Block {} is invalid: {}", height, kind))?;
+            parent.weight
+        };
+        let weight = parent_weight.saturating_add(self.fork_choice.weight(&block.header));
+        let extends_best = self.best.as_deref().is_none_or(|best| best == block.header.parent_hash);
+        let best_weight = self.best.as_deref().and_then(|best| self.weight(best)).unwrap_or(U256::ZERO);
+        if !extends_best && weight <= best_weight {
+            self.nodes.insert(hash, Node { header: block.header.clone(), weight, block: Some(block) });
+            return Ok(BlockStatus::SideChain);
+        }
+
+        if extends_best {
+            let header = block.header.clone();
+            chain.put_block(block)?;
+            self.nodes.insert(hash.clone(), Node { header, weight, block: None });
+            self.best = Some(hash);
+            mempool.revalidate(Vec::new(), chain.utxos(), chain.state());
+            return Ok(BlockStatus::Extended);
+        }
+
+        self.nodes.insert(hash.clone(), Node { header: block.header.clone(), weight, block: Some(block) });
+        self.reorganize(&hash, chain, mempool)
+    }
+
+    /// Переключает цепочку на ветку, оканчивающуюся блоком `tip`.
+    fn reorganize(&mut self, tip: &str, chain: &mut dyn ChainStore, mempool: &mut Mempool) -> Result<BlockStatus, String> {
+        // Поднимаемся от новой вершины до блока, который уже в цепочке
+        let mut branch = Vec::new();
+        let mut cursor = tip.to_string();
+        while !self.is_canonical(&cursor, chain) {
+            let node = &self.nodes[&cursor];
+            branch.push(cursor.clone());
+            cursor = node.header.parent_hash.clone();
+        }
+        branch.reverse();
+        let fork_height = self.nodes[&cursor].header.height;
+
+        let disconnected = chain.rewind_to(fork_height)?;
+        for block in &disconnected {
+            if let Some(node) = self.nodes.get_mut(&block.calculate_hash()) {
+                node.block = Some(block.clone());
+            }
+        }
+
+        for hash in &branch {
+            let block = self.nodes[hash].block.clone().ok_or_else(|| format!("Block {} body is missing", hash))?;
+            if let Err(e) = chain.put_block(block) {
+                // Возвращаем прежнюю цепочку и забываем недействительную ветку
+                chain.rewind_to(fork_height)?;
+                for block in &disconnected {
+                    chain.put_block(block.clone())?;
+                }
+                self.discard(hash);
+                self.mark_canonical(&disconnected);
+                return Err(format!("Reorganization to {} failed: {}", tip, e));
+            }
+        }
+
+        for hash in &branch {
+            if let Some(node) = self.nodes.get_mut(hash) {
+                node.block = None;
+            }
+        }
+        self.best = Some(tip.to_string());
+        log::info!("Reorganized chain at height {}: {} blocks disconnected, {} connected",
+            fork_height, disconnected.len(), branch.len());
+
+        let connected = branch.len();
+        let count = disconnected.len();
+        let orphaned = disconnected.into_iter().flat_map(|block| block.body.transactions).collect();
+        mempool.revalidate(orphaned, chain.utxos(), chain.state());
+        Ok(BlockStatus::Reorganized { disconnected: count, connected })
+    }
+
+    fn is_canonical(&self, hash: &str, chain: &dyn ChainStore) -> bool {
+        let height = match self.nodes.get(hash) {
+            Some(node) => node.header.height,
+            None => return false,
+        };
+        chain.headers().get(height as usize).is_some_and(|header| header.hash() == hash)
+    }
+
+    fn mark_canonical(&mut self, blocks: &[Block]) {
+        for block in blocks {
+            if let Some(node) = self.nodes.get_mut(&block.calculate_hash()) {
+                node.block = None;
+            }
+        }
+    }
+
+    /// Удаляет блок и всех его потомков.
+    fn discard(&mut self, hash: &str) {
+        let mut pending = vec![hash.to_string()];
+        while let Some(hash) = pending.pop() {
+            self.nodes.remove(&hash);
+            pending.extend(self.nodes.iter()
+                .filter(|(_, node)| node.header.parent_hash == hash)
+                .map(|(child, _)| child.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_store::MemoryChainStore;
+    use crate::consensus::PoW;
+    use crate::difficulty::next_bits;
+    use crate::fork_choice::{LongestChain, MostWork};
+    use crate::miner::{CancellationToken, MineResult, Miner};
+    use crate::state::State;
+    use crate::transaction::{Transaction, TxOutput, DEFAULT_CHAIN_ID};
+    use crate::wallet::{generate_address, keypair_from_seed};
+
+    fn seal(mut block: Block, branch: &[&Block]) -> Block {
+        let blocks: Vec<Block> = branch.iter().map(|b| (*b).clone()).collect();
+        if let Ok(root) = State::from_blocks(State::default(), &blocks).and_then(|state| state.state_root_after(&block)) {
+            block.header.state_root = root;
+        }
+        block.hash = Some(block.calculate_hash());
+        block
+    }
+
+    fn genesis() -> Block {
+        let alice = generate_address(&keypair_from_seed("alice").public);
+        let output = TxOutput { asset: "TRD".into(), recipient: alice, amount: 100 };
+        let tx = Transaction::new("genesis".into(), vec![output], 0);
+        seal(Block::new(0, "0".into(), 1675303065, vec![tx], "Hybrid".into()), &[])
+    }
+
+    fn addr(name: &str) -> String {
+        generate_address(&keypair_from_seed(name).public)
+    }
+
+    /// Блок от производителя с ключом из зерна `producer`.
+    fn child(branch: &[&Block], producer: &str, transactions: Vec<Transaction>) -> Block {
+        let parent = branch.last().unwrap();
+        let mut block = Block::new(parent.header.height + 1, parent.calculate_hash(), parent.header.timestamp + 60, transactions, "Hybrid".into());
+        block.header.producer = addr(producer);
+        block.insert_coinbase(DEFAULT_CHAIN_ID).unwrap();
+        let mut block = seal(block, branch);
+        block.sign(&keypair_from_seed(producer));
+        seal_hash(block)
+    }
+
+    fn transfer() -> Transaction {
+        let alice = keypair_from_seed("alice");
+        let output = TxOutput { asset: "TRD".into(), recipient: "bob".into(), amount: 10 };
+        let mut tx = Transaction::new(generate_address(&alice.public), vec![output], 1);
+        tx.sign(&alice);
+        tx
+    }
+
+    #[test]
+    fn test_reorg_to_longer_branch_reinjects_transactions() {
+        let mut chain = MemoryChainStore::new();
+        let mut mempool = Mempool::default();
+        let mut tree = BlockTree::new(Box::new(LongestChain));
+
+        let g = genesis();
+        let a1 = child(&[&g], "a", vec![transfer()]);
+        let b1 = child(&[&g], "b", Vec::new());
+        let b2 = child(&[&g, &b1], "b", Vec::new());
+
+        assert_eq!(tree.insert(g.clone(), &mut chain, &mut mempool), Ok(BlockStatus::Extended));
+        assert_eq!(tree.insert(a1.clone(), &mut chain, &mut mempool), Ok(BlockStatus::Extended));
+        assert_eq!(tree.insert(b1, &mut chain, &mut mempool), Ok(BlockStatus::SideChain));
+        assert!(tree.insert(a1, &mut chain, &mut mempool).is_err(), "Duplicate block must be rejected");
+        assert_eq!(chain.state().balance("bob", "TRD"), 10);
+
+        let status = tree.insert(b2.clone(), &mut chain, &mut mempool);
+        assert_eq!(status, Ok(BlockStatus::Reorganized { disconnected: 1, connected: 2 }));
+        assert_eq!(chain.tip_hash(), Some(b2.calculate_hash()));
+        assert_eq!(tree.best_hash(), Some(b2.calculate_hash().as_str()));
+        assert_eq!(chain.state().balance(&addr("a"), "TRD"), 0);
+        assert_eq!(chain.state().balance("bob", "TRD"), 0);
+        assert_eq!(mempool.transactions.len(), 1, "Orphaned transfer must return to the mempool");
+        assert_eq!(tree.ancestors(&b2.calculate_hash()).len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_branch_keeps_current_chain() {
+        let mut chain = MemoryChainStore::new();
+        let mut mempool = Mempool::default();
+        let mut tree = BlockTree::new(Box::new(LongestChain));
+
+        let g = genesis();
+        let a1 = child(&[&g], "a", Vec::new());
+        let b1 = child(&[&g], "b", Vec::new());
+        let mut b2 = child(&[&g, &b1], "b", Vec::new());
+        b2.header.state_root = "bad".into();
+        b2.sign(&keypair_from_seed("b"));
+        let b2 = seal_hash(b2);
+
+        tree.insert(g, &mut chain, &mut mempool).unwrap();
+        tree.insert(a1.clone(), &mut chain, &mut mempool).unwrap();
+        tree.insert(b1, &mut chain, &mut mempool).unwrap();
+        assert!(tree.insert(b2.clone(), &mut chain, &mut mempool).is_err());
+        assert_eq!(chain.tip_hash(), Some(a1.calculate_hash()));
+        assert_eq!(chain.state().balance(&addr("a"), "TRD"), a1.header.miner_reward);
+        assert!(!tree.contains(&b2.calculate_hash()));
+    }
+
+    fn seal_hash(mut block: Block) -> Block {
+        block.hash = Some(block.calculate_hash());
+        block
+    }
+
+    #[test]
+    fn test_unmined_block_gets_no_weight() {
+        let mut chain = MemoryChainStore::new();
+        let mut mempool = Mempool::default();
+        let mut plugins = PluginManager::new();
+        plugins.register_plugin(Box::new(PoW::default()));
+        let mut tree = BlockTree::new(Box::new(MostWork)).with_plugins(plugins);
+
+        let g = genesis();
+        let mut a1 = child(&[&g], "a", Vec::new());
+        a1.header.bits = next_bits(std::slice::from_ref(&g.header));
+        a1.sign(&keypair_from_seed("a"));
+        let a1 = match Miner::default().mine(a1, &CancellationToken::new(), |_| {}) {
+            MineResult::Found { block, .. } => *block,
+            _ => panic!("Block should be mined"),
+        };
+        tree.insert(g.clone(), &mut chain, &mut mempool).unwrap();
+        assert_eq!(tree.insert(a1.clone(), &mut chain, &mut mempool), Ok(BlockStatus::Extended));
+
+        // Нулевая цель дала бы блоку максимальный вес и форсировала реорганизацию
+        let mut forged = child(&[&g], "b", Vec::new());
+        forged.header.bits = 0;
+        forged.sign(&keypair_from_seed("b"));
+        let forged = seal_hash(forged);
+        assert!(tree.insert(forged.clone(), &mut chain, &mut mempool).is_err());
+        assert!(!tree.contains(&forged.calculate_hash()), "Rejected blocks must not be stored as side chains");
+        assert_eq!(chain.tip_hash(), Some(a1.calculate_hash()));
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::fs;
+use serde::{Serialize, Deserialize};
+use crate::asset_registry::AssetDefinition;
+use crate::block::Block;
+use crate::block_limits::BlockLimits;
+use crate::consensus;
+use crate::consensus_plugin::PluginManager;
+use crate::difficulty::DifficultyParams;
+use crate::fork_choice::{ForkChoice, LongestChain, MostWork};
+use crate::staking::StakingParams;
+use crate::state::State;
+use crate::timestamp_rules::TimestampRules;
+use crate::token_economy::{EmissionSchedule, FeeSplit, RewardPolicy, TokenEconomy, NATIVE_ASSET};
+use crate::transaction::{Transaction, TxOutput, TxType};
+use crate::vesting::VestingSchedule;
+use crate::wallet::is_valid_address;
+
+/// Механизм консенсуса сети. `Hybrid` требует, чтобы блок прошёл все плагины.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsensusEngine {
+    PoW,
+    PoS,
+    DPoS,
+    Tendermint,
+    PoSpace,
+    Hybrid,
+}
+
+impl ConsensusEngine {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConsensusEngine::PoW => "PoW",
+            ConsensusEngine::PoS => "PoS",
+            ConsensusEngine::DPoS => "DPoS",
+            ConsensusEngine::Tendermint => "Tendermint",
+            ConsensusEngine::PoSpace => "PoSpace",
+            ConsensusEngine::Hybrid => "Hybrid",
+        }
+    }
+
+    /// Плагины, которыми проверяются блоки сети.
+    pub fn plugins(&self, difficulty: &DifficultyParams) -> PluginManager {
+        let mut plugins = PluginManager::new();
+        let pow = || Box::new(consensus::PoW { difficulty: difficulty.clone() });
+        match self {
+            ConsensusEngine::PoW => plugins.register_plugin(pow()),
+            ConsensusEngine::PoS => plugins.register_plugin(Box::new(consensus::PoS)),
+            ConsensusEngine::DPoS => plugins.register_plugin(Box::new(consensus::DPoS)),
+            ConsensusEngine::Tendermint => plugins.register_plugin(Box::new(consensus::Tendermint)),
+            ConsensusEngine::PoSpace => plugins.register_plugin(Box::new(consensus::PoSpace)),
+            ConsensusEngine::Hybrid => {
+                plugins.register_plugin(pow());
+                plugins.register_plugin(Box::new(consensus::PoS));
+                plugins.register_plugin(Box::new(consensus::DPoS));
+                plugins.register_plugin(Box::new(consensus::Tendermint));
+                plugins.register_plugin(Box::new(consensus::PoSpace));
+            }
+        }
+        plugins
+    }
+
+    /// Для механизмов с работой в заголовке лучшая ветка — самая тяжёлая.
+    pub fn fork_choice(&self) -> Box<dyn ForkChoice> {
+        match self {
+            ConsensusEngine::PoW | ConsensusEngine::Hybrid => Box::new(MostWork),
+            _ => Box::new(LongestChain),
+        }
+    }
+}
+
+/// Начальная аллокация; с `vesting` токены разблокируются по графику.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Allocation {
+    pub address: String,
+    pub asset: String,
+    pub amount: u64,
+    #[serde(default)]
+    pub vesting: Option<VestingSchedule>,
+}
+
+/// Начальный валидатор и его стейк в нативном активе.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatorSpec {
+    pub address: String,
+    pub stake: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonetarySpec {
+    pub initial_supply: u64,
+    pub emission: EmissionSchedule,
+    #[serde(default)]
+    pub fees: FeeSplit,
+    pub transaction_fee: u64,
+}
+
+/// Спецификация сети: всё, из чего детерминированно выводятся генезис-блок
+/// и начальное состояние. Два узла с одинаковой спецификацией получают
+/// генезис с одинаковым хешем.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainSpec {
+    pub name: String,
+    pub chain_id: u64,
+    pub genesis_timestamp: u64,
+    pub consensus: ConsensusEngine,
+    pub difficulty: DifficultyParams,
+    pub monetary: MonetarySpec,
+    #[serde(default)]
+    pub limits: BlockLimits,
+    #[serde(default)]
+    pub timestamps: TimestampRules,
+    #[serde(default)]
+    pub staking: StakingParams,
+    /// Активы, кроме нативного, регистрируемые в генезисе.
+    #[serde(default)]
+    pub assets: Vec<AssetDefinition>,
+    pub allocations: Vec<Allocation>,
+    #[serde(default)]
+    pub validators: Vec<ValidatorSpec>,
+}
+
+/// Отправитель транзакций генезиса: они ничего не списывают и не подписываются.
+pub const GENESIS_SENDER: &str = "genesis";
+
+impl ChainSpec {
+    pub fn load(path: &str) -> Result<ChainSpec, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Failed to read chain spec {}: {}", path, e))?;
+        let spec: ChainSpec = serde_json::from_str(&data).map_err(|e| format!("Invalid chain spec {}: {}", path, e))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.difficulty.validate()?;
+        self.monetary.emission.validate()?;
+        self.monetary.fees.validate()?;
+        self.staking.validate()?;
+        if let Some(a) = self.allocations.iter().find(|a| a.address.is_empty() || a.asset.is_empty() || a.amount == 0) {
+            return Err(format!("Invalid genesis allocation {:?}", a));
+        }
+        for schedule in self.allocations.iter().filter_map(|a| a.vesting.as_ref()) {
+            schedule.validate()?;
+        }
+        for (i, asset) in self.assets.iter().enumerate() {
+            asset.validate()?;
+            if self.assets[..i].iter().any(|other| other.symbol == asset.symbol) {
+                return Err(format!("Asset {} is declared twice", asset.symbol));
+            }
+        }
+        for (asset, amount) in self.genesis_supply()? {
+            if asset == NATIVE_ASSET {
+                continue;
+            }
+            match self.assets.iter().find(|a| a.symbol == asset) {
+                Some(definition) if amount <= definition.max_supply => {}
+                Some(definition) => return Err(format!("Genesis allocates {} {}, above max supply {}", amount, asset, definition.max_supply)),
+                None => return Err(format!("Genesis allocates unregistered asset {}", asset)),
+            }
+        }
+        if self.limits.max_transactions == 0 || self.limits.max_block_gas == 0 || self.limits.max_block_size == 0 {
+            return Err("Block limits must be positive".into());
+        }
+        if self.timestamps.median_time_span == 0 {
+            return Err("Median time span must be positive".into());
+        }
+        if let Some(v) = self.validators.iter().find(|v| !is_valid_address(&v.address) || v.stake == 0) {
+            return Err(format!("Invalid genesis validator {:?}", v));
+        }
+        let genesis_supply = self.genesis_supply()?.get(NATIVE_ASSET).copied().unwrap_or(0);
+        if self.monetary.initial_supply != genesis_supply {
+            return Err(format!("Initial supply {} does not match genesis supply {} of {}", self.monetary.initial_supply, genesis_supply, NATIVE_ASSET));
+        }
+        Ok(())
+    }
+
+    /// Выпуск генезиса по активам: аллокации и стейки валидаторов.
+    pub fn genesis_supply(&self) -> Result<BTreeMap<String, u64>, String> {
+        let mut supply = BTreeMap::new();
+        let amounts = self.allocations.iter().map(|a| (a.asset.as_str(), a.amount))
+            .chain(self.validators.iter().map(|v| (NATIVE_ASSET, v.stake)));
+        for (asset, amount) in amounts {
+            let total: &mut u64 = supply.entry(asset.to_string()).or_default();
+            *total = total.checked_add(amount).ok_or_else(|| format!("Genesis supply of {} overflows u64", asset))?;
+        }
+        Ok(supply)
+    }
+
+    fn genesis_transaction(&self, tx_type: TxType, outputs: Vec<TxOutput>) -> Transaction {
+        let mut tx = Transaction::new(GENESIS_SENDER.into(), outputs, 0);
+        tx.tx_type = tx_type;
+        self.seal_genesis_transaction(tx)
+    }
+
+    fn seal_genesis_transaction(&self, mut tx: Transaction) -> Transaction {
+        tx.chain_id = self.chain_id;
+        tx.timestamp = self.genesis_timestamp;
+        tx.id = tx.calculate_id();
+        tx
+    }
+
+    /// Генезис-блок: IssueAsset-транзакции активов, транзакция свободных
+    /// аллокаций, по транзакции на каждую аллокацию с вестингом и, если заданы
+    /// валидаторы, Stake-транзакция с их начальными стейками.
+    pub fn genesis_block(&self) -> Block {
+        let mut transactions: Vec<Transaction> = self.assets.iter()
+            .map(|asset| self.seal_genesis_transaction(Transaction::new_issue_asset(GENESIS_SENDER.into(), asset.clone(), Vec::new(), 0)))
+            .collect();
+        let output = |a: &Allocation| TxOutput { asset: a.asset.clone(), recipient: a.address.clone(), amount: a.amount };
+        let allocations = self.allocations.iter().filter(|a| a.vesting.is_none()).map(output).collect();
+        transactions.push(self.genesis_transaction(TxType::Transfer, allocations));
+        for allocation in self.allocations.iter().filter(|a| a.vesting.is_some()) {
+            let mut tx = Transaction::new(GENESIS_SENDER.into(), vec![output(allocation)], 0);
+            tx.vesting = allocation.vesting.clone();
+            transactions.push(self.seal_genesis_transaction(tx));
+        }
+        if !self.validators.is_empty() {
+            let stakes = self.validators.iter()
+                .map(|v| TxOutput { asset: NATIVE_ASSET.into(), recipient: v.address.clone(), amount: v.stake })
+                .collect();
+            transactions.push(self.genesis_transaction(TxType::Stake, stakes));
+        }
+        let mut genesis = Block::new(0, "0".into(), self.genesis_timestamp, transactions, self.consensus.name().into());
+        genesis.header.miner_reward = 0;
+        genesis.header.transaction_fee = 0;
+        if let Ok(root) = self.initial_state().state_root_after(&genesis) {
+            genesis.header.state_root = root;
+        }
+        // Генезис не проходит проверку PoW: его хеш просто фиксирует содержимое
+        genesis.hash = Some(genesis.calculate_hash());
+        genesis
+    }
+
+    /// Пустое состояние сети до генезиса: идентификатор цепочки и параметры стейкинга.
+    pub fn initial_state(&self) -> State {
+        State::new(self.chain_id).with_staking_params(self.staking.clone())
+    }
+
+    pub fn genesis_state(&self) -> Result<State, String> {
+        State::from_blocks(self.initial_state(), &[self.genesis_block()])
+    }
+
+    /// Все правила проверки блока: плагины механизма консенсуса, лимиты
+    /// блока, правила времени и награда блока.
+    pub fn plugins(&self) -> PluginManager {
+        let mut plugins = self.consensus.plugins(&self.difficulty);
+        plugins.register_plugin(Box::new(self.limits.clone()));
+        plugins.register_plugin(Box::new(self.timestamps.clone()));
+        plugins.register_plugin(Box::new(RewardPolicy { schedule: self.monetary.emission.clone(), fees: self.monetary.fees.clone() }));
+        plugins
+    }
+
+    pub fn economy(&self) -> TokenEconomy {
+        TokenEconomy::new(self.monetary.initial_supply, self.monetary.fees.clone())
+    }
+}
+
+/// Спецификация из `chainspec.json` в корне репозитория — общая для тестов.
+#[cfg(test)]
+pub(crate) fn shipped_spec() -> ChainSpec {
+    ChainSpec::load(concat!(env!("CARGO_MANIFEST_DIR"), "/chainspec.json")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_is_deterministic() {
+        let spec = shipped_spec();
+        assert_eq!(spec.genesis_block().calculate_hash(), shipped_spec().genesis_block().calculate_hash());
+
+        let state = spec.genesis_state().unwrap();
+        assert_eq!(state.chain_id, spec.chain_id);
+        for allocation in &spec.allocations {
+            assert_eq!(state.balance(&allocation.address, &allocation.asset), allocation.amount);
+        }
+        for validator in &spec.validators {
+            assert_eq!(state.staking.validator_stake(&validator.address), validator.stake);
+        }
+        let vested = spec.allocations.iter().find(|a| a.vesting.is_some()).unwrap();
+        assert_eq!(state.locked_balance(&vested.address, &vested.asset), vested.amount, "Vesting allocations start locked");
+
+        let mut other = spec.clone();
+        other.chain_id += 1;
+        assert_ne!(other.genesis_block().calculate_hash(), spec.genesis_block().calculate_hash());
+    }
+
+    #[test]
+    fn test_state_replays_from_spec_parameters() {
+        let mut spec = shipped_spec();
+        spec.chain_id = 7;
+        spec.staking.unbonding_period += 5;
+        let path = std::env::temp_dir().join(format!("triad-spec-chain-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        Block::save_to_file(&[spec.genesis_block()], path).unwrap();
+
+        let state = State::load_from_file(path, spec.initial_state()).unwrap();
+        assert_eq!(state.chain_id, 7);
+        assert_eq!(state.staking_params, spec.staking);
+        assert!(State::load_from_file(path, State::default()).is_err(), "Genesis of another network must not replay on default parameters");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_invalid_spec() {
+        let mut spec = shipped_spec();
+        spec.difficulty.initial_bits = 0x2100_ffff;
+        assert!(spec.validate().is_err(), "Initial target above the pow limit must be rejected");
+
+        let mut spec = shipped_spec();
+        spec.allocations[0].amount = 0;
+        assert!(spec.validate().is_err());
+
+        let mut spec = shipped_spec();
+        spec.assets.clear();
+        assert!(spec.validate().is_err(), "Allocated assets must be registered");
+
+        let mut spec = shipped_spec();
+        spec.monetary.initial_supply += 1;
+        assert!(spec.validate().is_err(), "Initial supply must match the genesis allocations");
+
+        let mut spec = shipped_spec();
+        spec.allocations[0].amount = u64::MAX;
+        assert!(spec.validate().is_err(), "Overflowing genesis supply must be an error, not a panic");
+
+        let mut json = serde_json::to_value(shipped_spec()).unwrap();
+        json["monetary"]["burn_rate"] = serde_json::json!(0.01);
+        assert!(serde_json::from_value::<ChainSpec>(json).is_err(), "Leftover keys must not be ignored");
+
+        let mut spec = shipped_spec();
+        spec.staking.unbonding_period = 0;
+        assert!(spec.validate().is_err());
+
+        let mut spec = shipped_spec();
+        spec.validators[0].address = "validator".into();
+        assert!(spec.validate().is_err(), "Validators must have producer addresses");
+    }
+}
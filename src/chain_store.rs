@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::block::{Block, BlockHeader};
+use crate::block_store::{BlockStore, TxLocation};
+use crate::consensus_plugin::PluginManager;
+use crate::state::State;
+use crate::timestamp_rules::median_time_past;
+use crate::transaction::DEFAULT_CHAIN_ID;
+use crate::utxo::UtxoSet;
+use crate::verification::check_block;
+
+/// Хранилище цепочки: блоки лучшей ветки и состояние на её вершине.
+///
+/// `put_block` принимает только блок, продолжающий текущую вершину и
+/// прошедший `check_block` с плагинами консенсуса хранилища, и применяет его к состоянию
+/// атомарно: при ошибке не меняется ни состояние, ни сохранённые блоки.
+/// `rewind_to` откатывает цепочку при реорганизации.
+pub trait ChainStore: Send {
+    fn put_block(&mut self, block: Block) -> Result<(), String>;
+    /// Удаляет блоки выше `height`, возвращает их по порядку высоты и
+    /// восстанавливает состояние на блоке `height`.
+    fn rewind_to(&mut self, height: u64) -> Result<Vec<Block>, String>;
+    fn get_block(&self, hash: &str) -> Result<Option<Block>, String>;
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>, String>;
+    fn locate_transaction(&self, tx_id: &str) -> Option<TxLocation>;
+    /// Заголовки от генезиса до вершины.
+    fn headers(&self) -> &[BlockHeader];
+    fn state(&self) -> &State;
+    fn utxos(&self) -> &UtxoSet;
+
+    /// Заголовок вершины лучшей ветки.
+    fn best_tip(&self) -> Option<&BlockHeader> {
+        self.headers().last()
+    }
+
+    /// Median-time-past вершины: время, на которое можно опираться в
+    /// таймлоках (см. `timestamp_rules`).
+    fn median_time_past(&self, span: usize) -> Option<u64> {
+        median_time_past(self.headers(), span)
+    }
+
+    fn tip_hash(&self) -> Option<String> {
+        self.best_tip().map(|h| h.hash())
+    }
+
+    fn len(&self) -> usize {
+        self.headers().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.headers().is_empty()
+    }
+
+    fn blocks(&self) -> Result<Vec<Block>, String> {
+        (0..self.len() as u64)
+            .map(|height| self.get_by_height(height)?.ok_or_else(|| format!("Block {} is missing", height)))
+            .collect()
+    }
+}
+
+/// Заголовки и состояние на вершине — общая часть обеих реализаций.
+struct ChainTip {
+    headers: Vec<BlockHeader>,
+    state: State,
+}
+
+impl ChainTip {
+    fn new(state: State) -> Self {
+        ChainTip { headers: Vec::new(), state }
+    }
+
+    /// Состояние заново выводится из блоков, начиная с генезиса. Уже
+    /// принятые блоки проходят те же проверки, кроме плагинов консенсуса.
+    fn from_blocks<'a, I: IntoIterator<Item = &'a Block>>(initial: State, blocks: I) -> Result<ChainTip, String> {
+        let mut tip = ChainTip::new(initial);
+        let no_plugins = PluginManager::new();
+        for block in blocks {
+            let next = tip.next(block, &no_plugins)
+                .map_err(|e| format!("Stored block {} does not apply: {}", block.header.height, e))?;
+            tip.commit(block, next);
+        }
+        Ok(tip)
+    }
+
+    /// Проверяет блок относительно вершины (`check_block`) и применяет его к копии состояния.
+    fn next(&self, block: &Block, plugins: &PluginManager) -> Result<State, String> {
+        check_block(block, &self.headers, plugins)
+            .map_err(|kind| format!("Block {} is invalid: {}", block.header.height, kind))?;
+        let mut state = self.state.clone();
+        state.apply_block(block)?;
+        Ok(state)
+    }
+
+    fn commit(&mut self, block: &Block, state: State) {
+        self.headers.push(block.header.clone());
+        self.state = state;
+    }
+}
+
+/// Хранилище в памяти — для тестов и временных узлов.
+pub struct MemoryChainStore {
+    tip: ChainTip,
+    plugins: PluginManager,
+    blocks: Vec<Block>,
+    by_hash: HashMap<String, usize>,
+    tx_index: HashMap<String, TxLocation>,
+}
+
+impl Default for MemoryChainStore {
+    fn default() -> Self {
+        MemoryChainStore::with_chain_id(DEFAULT_CHAIN_ID)
+    }
+}
+
+impl MemoryChainStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_chain_id(chain_id: u64) -> Self {
+        MemoryChainStore::with_initial_state(State::new(chain_id))
+    }
+
+    /// Хранилище сети, заданной пустым состоянием `initial` (см. `ChainSpec::initial_state`).
+    pub fn with_initial_state(initial: State) -> Self {
+        MemoryChainStore {
+            tip: ChainTip::new(initial),
+            plugins: PluginManager::new(),
+            blocks: Vec::new(),
+            by_hash: HashMap::new(),
+            tx_index: HashMap::new(),
+        }
+    }
+
+    /// Плагины консенсуса, которые проходит каждый новый блок.
+    pub fn with_plugins(mut self, plugins: PluginManager) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    fn index(&mut self, block: &Block, position: usize) {
+        let hash = block.calculate_hash();
+        for (tx_position, tx) in block.body.transactions.iter().enumerate() {
+            self.tx_index.insert(tx.id.clone(), TxLocation {
+                block_hash: hash.clone(),
+                height: block.header.height,
+                position: tx_position as u32,
+            });
+        }
+        self.by_hash.insert(hash, position);
+    }
+}
+
+impl ChainStore for MemoryChainStore {
+    fn put_block(&mut self, block: Block) -> Result<(), String> {
+        let next = self.tip.next(&block, &self.plugins)?;
+        self.tip.commit(&block, next);
+        self.index(&block, self.blocks.len());
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    fn rewind_to(&mut self, height: u64) -> Result<Vec<Block>, String> {
+        let keep = (height as usize + 1).min(self.blocks.len());
+        // Новая вершина строится до изменения блоков: при ошибке хранилище остаётся прежним
+        let tip = ChainTip::from_blocks(self.tip.state.empty(), &self.blocks[..keep])?;
+        let removed = self.blocks.split_off(keep);
+        self.tip = tip;
+        self.by_hash.clear();
+        self.tx_index.clear();
+        for (position, block) in std::mem::take(&mut self.blocks).into_iter().enumerate() {
+            self.index(&block, position);
+            self.blocks.push(block);
+        }
+        Ok(removed)
+    }
+
+    fn get_block(&self, hash: &str) -> Result<Option<Block>, String> {
+        Ok(self.by_hash.get(hash).map(|&i| self.blocks[i].clone()))
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>, String> {
+        Ok(self.blocks.get(height as usize).cloned())
+    }
+
+    fn locate_transaction(&self, tx_id: &str) -> Option<TxLocation> {
+        self.tx_index.get(tx_id).cloned()
+    }
+
+    fn headers(&self) -> &[BlockHeader] {
+        &self.tip.headers
+    }
+
+    fn state(&self) -> &State {
+        &self.tip.state
+    }
+
+    fn utxos(&self) -> &UtxoSet {
+        &self.tip.state.utxos
+    }
+
+    fn blocks(&self) -> Result<Vec<Block>, String> {
+        Ok(self.blocks.clone())
+    }
+}
+
+/// Хранилище узла: блоки в `BlockStore` на диске, заголовки и состояние в
+/// памяти. Состояние не сохраняется отдельно, а восстанавливается при открытии
+/// повторным применением сохранённых блоков.
+pub struct FileChainStore {
+    tip: ChainTip,
+    store: BlockStore,
+    plugins: PluginManager,
+}
+
+impl FileChainStore {
+    /// Открывает хранилище и воспроизводит блоки поверх пустого состояния сети `initial`.
+    pub fn open<P: AsRef<Path>>(dir: P, initial: State) -> Result<FileChainStore, String> {
+        let store = BlockStore::open(dir).map_err(|e| e.to_string())?;
+        let tip = ChainTip::from_blocks(initial, &store.load_all().map_err(|e| e.to_string())?)?;
+        Ok(FileChainStore { tip, store, plugins: PluginManager::new() })
+    }
+
+    /// Плагины консенсуса, которые проходит каждый новый блок.
+    pub fn with_plugins(mut self, plugins: PluginManager) -> Self {
+        self.plugins = plugins;
+        self
+    }
+}
+
+impl ChainStore for FileChainStore {
+    fn put_block(&mut self, block: Block) -> Result<(), String> {
+        let next = self.tip.next(&block, &self.plugins)?;
+        self.store.append(&block).map_err(|e| e.to_string())?;
+        self.tip.commit(&block, next);
+        Ok(())
+    }
+
+    /// Состояние не хранит истории, поэтому после отката оно пересчитывается
+    /// по оставшимся блокам.
+    fn rewind_to(&mut self, height: u64) -> Result<Vec<Block>, String> {
+        let blocks = self.store.load_all().map_err(|e| e.to_string())?;
+        let keep = (height as usize + 1).min(blocks.len());
+        let tip = ChainTip::from_blocks(self.tip.state.empty(), &blocks[..keep])?;
+        let removed = self.store.truncate(height).map_err(|e| e.to_string())?;
+        self.tip = tip;
+        Ok(removed)
+    }
+
+    fn get_block(&self, hash: &str) -> Result<Option<Block>, String> {
+        self.store.get_by_hash(hash).map_err(|e| e.to_string())
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>, String> {
+        self.store.get_by_height(height).map_err(|e| e.to_string())
+    }
+
+    fn locate_transaction(&self, tx_id: &str) -> Option<TxLocation> {
+        self.store.locate_transaction(tx_id).cloned()
+    }
+
+    fn headers(&self) -> &[BlockHeader] {
+        &self.tip.headers
+    }
+
+    fn state(&self) -> &State {
+        &self.tip.state
+    }
+
+    fn utxos(&self) -> &UtxoSet {
+        &self.tip.state.utxos
+    }
+
+    fn blocks(&self) -> Result<Vec<Block>, String> {
+        self.store.load_all().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_plugin::{ChainContext, ConsensusPlugin};
+    use crate::transaction::{Transaction, TxOutput};
+    use crate::wallet::{generate_address, keypair_from_seed};
+
+    fn genesis() -> Block {
+        let output = TxOutput { asset: "TRD".into(), recipient: "alice".into(), amount: 100 };
+        let tx = Transaction::new("genesis".into(), vec![output], 0);
+        let mut block = Block::new(0, "0".into(), 1675303065, vec![tx], "Hybrid".into());
+        block.header.state_root = State::default().state_root_after(&block).unwrap();
+        block.hash = Some(block.calculate_hash());
+        block
+    }
+
+    fn child(parent: &Block) -> Block {
+        let keypair = keypair_from_seed("miner");
+        let mut block = Block::new(1, parent.calculate_hash(), 1675304065, Vec::new(), "Hybrid".into());
+        block.header.producer = generate_address(&keypair.public);
+        block.insert_coinbase(DEFAULT_CHAIN_ID).unwrap();
+        block.header.state_root = State::from_blocks(State::default(), std::slice::from_ref(parent)).unwrap()
+            .state_root_after(&block).unwrap();
+        block.sign(&keypair);
+        block.hash = Some(block.calculate_hash());
+        block
+    }
+
+    fn exercise(store: &mut dyn ChainStore) {
+        let genesis = genesis();
+        let block = child(&genesis);
+        store.put_block(genesis.clone()).unwrap();
+
+        let mut orphan = block.clone();
+        orphan.header.parent_hash = "unknown".into();
+        orphan.hash = Some(orphan.calculate_hash());
+        assert!(store.put_block(orphan).is_err(), "Block must extend the tip");
+        let mut garbage = block.clone();
+        garbage.header.merkle_root = "garbage".into();
+        garbage.hash = Some(garbage.calculate_hash());
+        assert!(store.put_block(garbage).is_err(), "Merkle root must match the body");
+        let mut unsigned = block.clone();
+        unsigned.header.signature = None;
+        unsigned.hash = Some(unsigned.calculate_hash());
+        assert!(store.put_block(unsigned).is_err(), "Producer signature is required");
+        assert_eq!(store.len(), 1);
+
+        store.put_block(block.clone()).unwrap();
+        assert_eq!(store.tip_hash(), Some(block.calculate_hash()));
+        assert_eq!(store.state().balance("alice", "TRD"), 100);
+        assert_eq!(store.state().balance(&block.header.producer, "TRD"), block.header.miner_reward);
+        assert_eq!(store.get_by_height(1).unwrap().unwrap().calculate_hash(), block.calculate_hash());
+        assert_eq!(store.get_block(&genesis.calculate_hash()).unwrap().unwrap().header.height, 0);
+        let tx_id = &genesis.body.transactions[0].id;
+        assert_eq!(store.locate_transaction(tx_id).unwrap().height, 0);
+        assert_eq!(store.blocks().unwrap().len(), 2);
+
+        let removed = store.rewind_to(0).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(store.state().balance(&block.header.producer, "TRD"), 0);
+        assert!(store.get_block(&block.calculate_hash()).unwrap().is_none());
+        store.put_block(block).unwrap();
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise(&mut MemoryChainStore::new());
+    }
+
+    struct RejectAll;
+
+    impl ConsensusPlugin for RejectAll {
+        fn name(&self) -> &'static str {
+            "RejectAll"
+        }
+
+        fn validate(&self, _block: &Block, _ctx: &ChainContext) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_consensus_plugins_guard_put_block() {
+        let mut plugins = PluginManager::new();
+        plugins.register_plugin(Box::new(RejectAll));
+        let mut store = MemoryChainStore::new().with_plugins(plugins);
+        let genesis = genesis();
+        store.put_block(genesis.clone()).unwrap();
+        assert!(store.put_block(child(&genesis)).is_err(), "Blocks rejected by a plugin must not be stored");
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_file_store_restores_state() {
+        let dir = std::env::temp_dir().join(format!("triad-chain-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        exercise(&mut FileChainStore::open(&dir, State::default()).unwrap());
+
+        let reopened = FileChainStore::open(&dir, State::default()).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.state().balance("alice", "TRD"), 100);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
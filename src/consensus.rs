@@ -1,74 +1,85 @@
 use crate::block::Block;
+use crate::consensus_plugin::ChainContext;
+use crate::difficulty::{next_bits_with, DifficultyParams};
 use log::{info, warn};
 
 /// Базовый трейт для консенсусных алгоритмов.
 pub trait Consensus {
-    fn validate(&self, block: &Block) -> bool;
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool;
 }
 
 /// Реализации базовых механизмов консенсуса.
-pub struct PoW;
+#[derive(Default)]
+pub struct PoW {
+    pub difficulty: DifficultyParams,
+}
 pub struct PoS;
 pub struct DPoS;
 pub struct Tendermint;
 pub struct PoSpace;
 
 impl Consensus for PoW {
-    fn validate(&self, block: &Block) -> bool {
-        let target = "0000";
-        let valid = block.hash.as_deref().unwrap_or("").starts_with(target);
+    /// Цель блока должна совпадать с вычисленной из истории цепочки,
+    /// а пересчитанный хеш как число — не превышать её.
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        let required = next_bits_with(ctx.ancestors, &self.difficulty);
+        if block.header.bits != required {
+            warn!("Block {} declares bits {:08x}, required {:08x}", block.header.height, block.header.bits, required);
+            return false;
+        }
+        let valid = block.verify_hash() && block.header.meets_target();
         if valid {
-            info!("Block {} valid with PoW", block.index);
+            info!("Block {} valid with PoW", block.header.height);
         } else {
-            warn!("Block {} invalid with PoW", block.index);
+            warn!("Block {} invalid with PoW", block.header.height);
         }
         valid
     }
 }
 
 impl Consensus for PoS {
-    fn validate(&self, block: &Block) -> bool {
-        let valid = block.index % 2 == 0;
+    fn validate(&self, block: &Block, _ctx: &ChainContext) -> bool {
+        let valid = block.header.height.is_multiple_of(2);
         if valid {
-            info!("Block {} valid with PoS", block.index);
+            info!("Block {} valid with PoS", block.header.height);
         } else {
-            warn!("Block {} invalid with PoS", block.index);
+            warn!("Block {} invalid with PoS", block.header.height);
         }
         valid
     }
 }
 
 impl Consensus for DPoS {
-    fn validate(&self, block: &Block) -> bool {
-        let valid = block.index % 3 == 0;
+    fn validate(&self, block: &Block, _ctx: &ChainContext) -> bool {
+        let valid = block.header.height.is_multiple_of(3);
         if valid {
-            info!("Block {} valid with DPoS", block.index);
+            info!("Block {} valid with DPoS", block.header.height);
         } else {
-            warn!("Block {} invalid with DPoS", block.index);
+            warn!("Block {} invalid with DPoS", block.header.height);
         }
         valid
     }
 }
 
 impl Consensus for Tendermint {
-    fn validate(&self, block: &Block) -> bool {
-        let valid = block.index % 4 == 0;
+    fn validate(&self, block: &Block, _ctx: &ChainContext) -> bool {
+        let valid = block.header.height.is_multiple_of(4);
         if valid {
-            info!("Block {} valid with Tendermint", block.index);
+            info!("Block {} valid with Tendermint", block.header.height);
         } else {
-            warn!("Block {} invalid with Tendermint", block.index);
+            warn!("Block {} invalid with Tendermint", block.header.height);
         }
         valid
     }
 }
 
 impl Consensus for PoSpace {
-    fn validate(&self, block: &Block) -> bool {
-        let valid = block.index % 5 == 0;
+    fn validate(&self, block: &Block, _ctx: &ChainContext) -> bool {
+        let valid = block.header.height.is_multiple_of(5);
         if valid {
-            info!("Block {} valid with PoSpace", block.index);
+            info!("Block {} valid with PoSpace", block.header.height);
         } else {
-            warn!("Block {} invalid with PoSpace", block.index);
+            warn!("Block {} invalid with PoSpace", block.header.height);
         }
         valid
     }
@@ -82,8 +93,8 @@ impl ConsensusPlugin for PoW {
     fn name(&self) -> &'static str {
         "PoW"
     }
-    fn validate(&self, block: &Block) -> bool {
-        <Self as Consensus>::validate(self, block)
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        <Self as Consensus>::validate(self, block, ctx)
     }
 }
 
@@ -91,8 +102,8 @@ impl ConsensusPlugin for PoS {
     fn name(&self) -> &'static str {
         "PoS"
     }
-    fn validate(&self, block: &Block) -> bool {
-        <Self as Consensus>::validate(self, block)
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        <Self as Consensus>::validate(self, block, ctx)
     }
 }
 
@@ -100,8 +111,8 @@ impl ConsensusPlugin for DPoS {
     fn name(&self) -> &'static str {
         "DPoS"
     }
-    fn validate(&self, block: &Block) -> bool {
-        <Self as Consensus>::validate(self, block)
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        <Self as Consensus>::validate(self, block, ctx)
     }
 }
 
@@ -109,8 +120,8 @@ impl ConsensusPlugin for Tendermint {
     fn name(&self) -> &'static str {
         "Tendermint"
     }
-    fn validate(&self, block: &Block) -> bool {
-        <Self as Consensus>::validate(self, block)
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        <Self as Consensus>::validate(self, block, ctx)
     }
 }
 
@@ -118,7 +129,7 @@ impl ConsensusPlugin for PoSpace {
     fn name(&self) -> &'static str {
         "PoSpace"
     }
-    fn validate(&self, block: &Block) -> bool {
-        <Self as Consensus>::validate(self, block)
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        <Self as Consensus>::validate(self, block, ctx)
     }
 }
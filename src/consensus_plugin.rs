@@ -1,10 +1,17 @@
-use crate::block::Block;
+use crate::block::{Block, BlockHeader};
+
+/// Контекст цепочки, относительно которого проверяется блок.
+pub struct ChainContext<'a> {
+    /// Заголовки от генезиса до родителя проверяемого блока.
+    pub ancestors: &'a [BlockHeader],
+}
 
 pub trait ConsensusPlugin: Send + Sync {
     fn name(&self) -> &'static str;
-    fn validate(&self, block: &Block) -> bool;
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool;
 }
 
+#[derive(Default)]
 pub struct PluginManager {
     plugins: Vec<Box<dyn ConsensusPlugin>>,
 }
@@ -18,10 +25,15 @@ impl PluginManager {
         self.plugins.push(plugin);
     }
 
-    pub fn validate_block(&self, block: &Block) -> bool {
+    /// Имя первого плагина, отклонившего блок.
+    pub fn rejecting_plugin(&self, block: &Block, ctx: &ChainContext) -> Option<&'static str> {
+        self.plugins.iter().find(|plugin| !plugin.validate(block, ctx)).map(|plugin| plugin.name())
+    }
+
+    pub fn validate_block(&self, block: &Block, ctx: &ChainContext) -> bool {
         self.plugins.iter().all(|plugin| {
-            println!("Plugin {} validating block {}", plugin.name(), block.index);
-            plugin.validate(block)
+            println!("Plugin {} validating block {}", plugin.name(), block.header.height);
+            plugin.validate(block, ctx)
         })
     }
 }
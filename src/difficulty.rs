@@ -0,0 +1,133 @@
+use serde::{Serialize, Deserialize};
+use crate::block::BlockHeader;
+use crate::target::{work_for_target, U256};
+
+/// Желаемый интервал между блоками, в секундах.
+pub const TARGET_BLOCK_TIME: u64 = 60;
+/// Сколько последних блоков учитывается при расчёте среднего времени.
+pub const RETARGET_WINDOW: usize = 10;
+/// Самая лёгкая допустимая цель (около 2^248).
+pub const POW_LIMIT_BITS: u32 = 0x2000_ffff;
+/// Цель первых блоков: 16 ведущих нулевых бит.
+pub const INITIAL_BITS: u32 = 0x1f00_ffff;
+/// За один пересчёт цель меняется не более чем в столько раз.
+pub const MAX_ADJUSTMENT_FACTOR: u64 = 4;
+
+/// Параметры пересчёта сложности; задаются спецификацией сети,
+/// по умолчанию — константы выше.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DifficultyParams {
+    pub target_block_time: u64,
+    pub retarget_window: usize,
+    pub pow_limit_bits: u32,
+    pub initial_bits: u32,
+    pub max_adjustment_factor: u64,
+}
+
+impl Default for DifficultyParams {
+    fn default() -> Self {
+        DifficultyParams {
+            target_block_time: TARGET_BLOCK_TIME,
+            retarget_window: RETARGET_WINDOW,
+            pow_limit_bits: POW_LIMIT_BITS,
+            initial_bits: INITIAL_BITS,
+            max_adjustment_factor: MAX_ADJUSTMENT_FACTOR,
+        }
+    }
+}
+
+impl DifficultyParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.target_block_time == 0 || self.retarget_window == 0 || self.max_adjustment_factor == 0 {
+            return Err("Block time, retarget window and adjustment factor must be positive".into());
+        }
+        let limit = U256::from_compact(self.pow_limit_bits).filter(|t| !t.is_zero())
+            .ok_or("Invalid pow limit bits")?;
+        match U256::from_compact(self.initial_bits) {
+            Some(initial) if !initial.is_zero() && initial <= limit => Ok(()),
+            _ => Err("Initial bits must be a valid target not above the pow limit".into()),
+        }
+    }
+}
+
+pub fn pow_limit() -> U256 {
+    U256::from_compact(POW_LIMIT_BITS).expect("valid pow limit")
+}
+
+/// `next_bits_with` с параметрами по умолчанию.
+pub fn next_bits(ancestors: &[BlockHeader]) -> u32 {
+    next_bits_with(ancestors, &DifficultyParams::default())
+}
+
+/// Цель (в формате nBits), которую обязан нести следующий блок, вычисляемая
+/// только из истории цепочки (`ancestors` — заголовки от генезиса до родителя).
+///
+/// Новая цель = цель родителя * фактическое время окна / ожидаемое время окна,
+/// где окно — последние `retarget_window` блоков. Фактическое время
+/// ограничивается в `max_adjustment_factor` раз в обе стороны, результат —
+/// пределом `pow_limit_bits`.
+pub fn next_bits_with(ancestors: &[BlockHeader], params: &DifficultyParams) -> u32 {
+    // Генезис не майнится, поэтому первые блоки идут с начальной целью.
+    if ancestors.len() < 2 {
+        return params.initial_bits;
+    }
+    let parent = &ancestors[ancestors.len() - 1];
+    let window = &ancestors[ancestors.len().saturating_sub(params.retarget_window + 1)..];
+    let expected = params.target_block_time * (window.len() as u64 - 1);
+    let actual = parent.timestamp.saturating_sub(window[0].timestamp)
+        .clamp(expected / params.max_adjustment_factor, expected * params.max_adjustment_factor);
+
+    let limit = U256::from_compact(params.pow_limit_bits).unwrap_or_else(pow_limit);
+    let current = U256::from_compact(parent.bits).filter(|t| !t.is_zero()).unwrap_or(limit).min(limit);
+    let next = match current.checked_mul_u64(actual) {
+        Some(scaled) => scaled.div_u64(expected),
+        None => current.div_u64(expected).checked_mul_u64(actual).unwrap_or(limit),
+    };
+    next.min(limit).to_compact()
+}
+
+/// Накопленная работа цепочки: сумма ожидаемых попыток по всем блокам, кроме генезиса.
+pub fn chain_work(headers: &[BlockHeader]) -> U256 {
+    headers.iter()
+        .filter(|h| h.height > 0)
+        .filter_map(|h| U256::from_compact(h.bits))
+        .fold(U256::ZERO, |acc, target| acc.saturating_add(work_for_target(&target)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(spacing: u64, bits: u32, count: u64) -> Vec<BlockHeader> {
+        (0..count).map(|height| BlockHeader {
+            height,
+            timestamp: 1_000_000 + height * spacing,
+            bits: if height == 0 { 0 } else { bits },
+            ..BlockHeader::default()
+        }).collect()
+    }
+
+    fn target(bits: u32) -> U256 {
+        U256::from_compact(bits).unwrap()
+    }
+
+    #[test]
+    fn test_retarget_follows_block_time() {
+        assert_eq!(next_bits(&chain(60, 0, 1)), INITIAL_BITS);
+        assert_eq!(next_bits(&chain(TARGET_BLOCK_TIME, INITIAL_BITS, 20)), INITIAL_BITS);
+        // Блоки вдвое быстрее — цель вдвое меньше (сложнее).
+        assert_eq!(target(next_bits(&chain(30, INITIAL_BITS, 20))), target(INITIAL_BITS).div_u64(2));
+        // Слишком быстрые блоки — ограничение в 4 раза.
+        assert_eq!(target(next_bits(&chain(1, INITIAL_BITS, 20))), target(INITIAL_BITS).div_u64(4));
+        // Медленные блоки не опускают сложность ниже предела.
+        assert_eq!(next_bits(&chain(1000, POW_LIMIT_BITS, 20)), POW_LIMIT_BITS);
+    }
+
+    #[test]
+    fn test_chain_work_accumulates() {
+        let headers = chain(60, INITIAL_BITS, 3);
+        let single = work_for_target(&target(INITIAL_BITS));
+        assert_eq!(chain_work(&headers), single.saturating_add(single));
+    }
+}
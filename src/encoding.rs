@@ -0,0 +1,142 @@
+use sha2::{Sha256, Digest};
+
+/// Теги доменного разделения: каждый тип сообщения хешируется и подписывается
+/// со своим префиксом, чтобы прообраз одного вида нельзя было выдать за другой.
+pub mod tag {
+    pub const TX_ID: &str = "TRIAD/tx-id/v1";
+    pub const TX_SIGNATURE: &str = "TRIAD/tx-sig/v1";
+    pub const BLOCK_HASH: &str = "TRIAD/block-hash/v1";
+    pub const BLOCK_SIGNATURE: &str = "TRIAD/block-sig/v1";
+    pub const MERKLE_LEAF: &str = "TRIAD/merkle-leaf/v1";
+    pub const MERKLE_NODE: &str = "TRIAD/merkle-node/v1";
+    pub const STATE_ROOT: &str = "TRIAD/state-root/v1";
+}
+
+/// Типы, имеющие каноническое бинарное представление.
+pub trait Encode {
+    fn encode(&self, enc: &mut Encoder);
+}
+
+/// Детерминированный кодировщик: целые числа пишутся в big-endian фиксированной
+/// ширины, байтовые строки и списки — с префиксом длины (u64).
+#[derive(Clone)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(domain: &str) -> Self {
+        let mut enc = Encoder { buf: Vec::new() };
+        enc.put_str(domain);
+        enc
+    }
+
+    pub fn put_u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn put_u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn put_u64(&mut self, value: u64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn put_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.put_u64(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn put_str(&mut self, value: &str) -> &mut Self {
+        self.put_bytes(value.as_bytes())
+    }
+
+    pub fn put<T: Encode + ?Sized>(&mut self, value: &T) -> &mut Self {
+        value.encode(self);
+        self
+    }
+
+    pub fn put_option<T: Encode>(&mut self, value: &Option<T>) -> &mut Self {
+        match value {
+            None => { self.put_u8(0); }
+            Some(v) => { self.put_u8(1); v.encode(self); }
+        }
+        self
+    }
+
+    pub fn put_list<T: Encode>(&mut self, items: &[T]) -> &mut Self {
+        self.put_u64(items.len() as u64);
+        for item in items {
+            item.encode(self);
+        }
+        self
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(&self.buf).into()
+    }
+
+    pub fn hash_hex(&self) -> String {
+        to_hex(&self.hash())
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, enc: &mut Encoder) { enc.put_u32(*self); }
+}
+
+impl Encode for u64 {
+    fn encode(&self, enc: &mut Encoder) { enc.put_u64(*self); }
+}
+
+impl Encode for str {
+    fn encode(&self, enc: &mut Encoder) { enc.put_str(self); }
+}
+
+impl Encode for String {
+    fn encode(&self, enc: &mut Encoder) { enc.put_str(self); }
+}
+
+impl Encode for Vec<u8> {
+    fn encode(&self, enc: &mut Encoder) { enc.put_bytes(self); }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_prefix_removes_ambiguity() {
+        let mut a = Encoder::new(tag::TX_ID);
+        a.put_str("1").put_str("23");
+        let mut b = Encoder::new(tag::TX_ID);
+        b.put_str("12").put_str("3");
+        assert_ne!(a.hash(), b.hash());
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let mut a = Encoder::new(tag::TX_ID);
+        a.put_u64(7);
+        let mut b = Encoder::new(tag::TX_SIGNATURE);
+        b.put_u64(7);
+        assert_ne!(a.hash(), b.hash());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::block::BlockHeader;
+use crate::target::{work_for_target, U256};
+
+/// Правило выбора лучшей ветки. Вес ветки — сумма весов её блоков;
+/// лучшей считается самая тяжёлая ветка, при равенстве остаётся текущая.
+pub trait ForkChoice: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn weight(&self, header: &BlockHeader) -> U256;
+}
+
+/// PoW: ветка с наибольшей накопленной работой (см. `difficulty::chain_work`).
+pub struct MostWork;
+
+/// Самая длинная ветка — для механизмов без работы в заголовке.
+pub struct LongestChain;
+
+impl ForkChoice for MostWork {
+    fn name(&self) -> &'static str {
+        "MostWork"
+    }
+
+    fn weight(&self, header: &BlockHeader) -> U256 {
+        if header.height == 0 {
+            return U256::ZERO;
+        }
+        U256::from_compact(header.bits).map(|target| work_for_target(&target)).unwrap_or(U256::ZERO)
+    }
+}
+
+impl ForkChoice for LongestChain {
+    fn name(&self) -> &'static str {
+        "LongestChain"
+    }
+
+    fn weight(&self, _header: &BlockHeader) -> U256 {
+        U256::ONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::{INITIAL_BITS, POW_LIMIT_BITS};
+
+    #[test]
+    fn test_harder_block_weighs_more() {
+        let header = |bits| BlockHeader { height: 1, bits, ..BlockHeader::default() };
+        let hard = MostWork.weight(&header(INITIAL_BITS));
+        let easy = MostWork.weight(&header(POW_LIMIT_BITS));
+        assert!(hard > easy);
+        assert_eq!(LongestChain.weight(&header(INITIAL_BITS)), LongestChain.weight(&header(POW_LIMIT_BITS)));
+    }
+}
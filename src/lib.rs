@@ -9,4 +9,23 @@ pub mod smart_contract;
 pub mod mempool;
 pub mod p2p_server;
 pub mod rest_api;
-mod consensus_plugin;
+pub mod consensus_plugin;
+pub mod encoding;
+pub mod utxo;
+pub mod state;
+pub mod merkle;
+pub mod difficulty;
+pub mod target;
+pub mod miner;
+pub mod block_store;
+pub mod chain_store;
+pub mod fork_choice;
+pub mod block_tree;
+pub mod verification;
+pub mod chain_spec;
+pub mod block_limits;
+pub mod timestamp_rules;
+pub mod supply_audit;
+pub mod asset_registry;
+pub mod vesting;
+pub mod staking;
@@ -1,105 +1,165 @@
-use chrono::Utc;
-use ed25519_dalek::{Keypair};
-use rand::rngs::OsRng;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use log::{info, warn, error};
-use crate::external_adapter::BlockchainAdapter;
-use crate::rest_api::AppState;
-
-mod transaction;
-mod block;
-mod wallet;
-mod token_economy;
-mod consensus;
-mod consensus_plugin;
-mod atomic_swap;
-mod external_adapter;
-mod smart_contract;
-mod mempool;
-mod p2p_server;
-mod rest_api;
-
-use transaction::{Transaction, TxType, TxOutput};
-use block::Block;
-use wallet::{TokenWallet, generate_address};
-use token_economy::TokenEconomy;
-use external_adapter::{DefaultBlockchainAdapter};
-use consensus_plugin::PluginManager;
+use my_blockchain::external_adapter::BlockchainAdapter;
+use my_blockchain::{mempool, p2p_server, rest_api};
 
+use my_blockchain::transaction::{Transaction, TxType, TxOutput};
+use my_blockchain::block::Block;
+use my_blockchain::wallet::{generate_address, keypair_from_seed};
+use my_blockchain::external_adapter::{DefaultBlockchainAdapter};
+use my_blockchain::consensus_plugin::ChainContext;
+use my_blockchain::difficulty::next_bits_with;
+use my_blockchain::miner::{CancellationToken, MineResult, Miner, StaleWatch};
+use my_blockchain::chain_store::{ChainStore, FileChainStore};
+use my_blockchain::block_tree::BlockTree;
+use my_blockchain::block_store::BlockStore;
+use my_blockchain::chain_spec::ChainSpec;
+use my_blockchain::verification::verify_chain;
 
+/// Каталог, в котором узел хранит блоки между перезапусками.
+const DATA_DIR: &str = "chaindata";
+/// Спецификация сети по умолчанию; другой файл задаётся через `--chain-spec <путь>`.
+const DEFAULT_CHAIN_SPEC: &str = "chainspec.json";
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1).cloned())
+}
+
+/// Режим `--verify`: полная проверка сохранённой цепочки без запуска узла.
+fn verify_stored_chain(spec: &ChainSpec) -> i32 {
+    let blocks = match BlockStore::open(DATA_DIR).and_then(|store| store.load_all()) {
+        Ok(blocks) => blocks,
+        Err(e) => {
+            error!("Failed to read block store {}: {}", DATA_DIR, e);
+            return 2;
+        }
+    };
+    if let Some(genesis) = blocks.first() {
+        if genesis.calculate_hash() != spec.genesis_block().calculate_hash() {
+            error!("Genesis block in {} does not match chain spec {}", DATA_DIR, spec.name);
+            return 1;
+        }
+    }
+    match verify_chain(&blocks, spec.initial_state(), &spec.plugins()) {
+        Ok(()) => {
+            info!("Chain of {} blocks in {} is valid", blocks.len(), DATA_DIR);
+            0
+        }
+        Err(e) => {
+            error!("Chain verification failed: {}", e);
+            1
+        }
+    }
+}
 
 fn main() {
     env_logger::init();
-    let mut rng = OsRng;
-    let app_state = Arc::new(Mutex::new(AppState {
-        blockchain: Vec::new(),
+    let spec_path = arg_value("--chain-spec").unwrap_or_else(|| DEFAULT_CHAIN_SPEC.into());
+    let spec = match ChainSpec::load(&spec_path) {
+        Ok(spec) => spec,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if std::env::args().any(|arg| arg == "--verify") {
+        std::process::exit(verify_stored_chain(&spec));
+    }
+    // Перезапуск узла: цепочка и состояние восстанавливаются из хранилища без перемайнинга
+    let chain = FileChainStore::open(DATA_DIR, spec.initial_state()).expect("Failed to open chain store").with_plugins(spec.plugins());
+    if !chain.is_empty() {
+        let genesis = chain.get_by_height(0).ok().flatten().map(|b| b.calculate_hash());
+        if genesis != Some(spec.genesis_block().calculate_hash()) {
+            error!("Chain data in {} was created from a different chain spec than {}", DATA_DIR, spec_path);
+            std::process::exit(2);
+        }
+        info!("Restored {} blocks from {}", chain.len(), DATA_DIR);
+    }
+    let app_state = Arc::new(Mutex::new(rest_api::AppState {
+        spec: spec.clone(),
+        chain: Box::new(chain),
         mempool: Arc::new(Mutex::new(mempool::Mempool::default())),
+        economy: spec.economy(),
     }));
-    
-        let app_state = Arc::new(Mutex::new(rest_api::AppState {
-            blockchain: Vec::new(),
-            mempool: Arc::new(Mutex::new(mempool::Mempool::default())),
-        }));
-        
-        
-
-    
+    let mut tree = BlockTree::from_chain(app_state.lock().unwrap().chain.as_ref(), spec.consensus.fork_choice()).with_plugins(spec.plugins());
+    let plugin_manager = spec.plugins();
+
     // Запуск P2P-сервера
     {
-        let mempool_clone = app_state.lock().unwrap().mempool.clone();
+        let app_state_clone = Arc::clone(&app_state);
         thread::spawn(move || {
-            p2p_server::start_p2p_server(mempool_clone);
+            p2p_server::start_p2p_server(app_state_clone);
         });
     }
 
-    let miner_keypair = Keypair::generate(&mut rng);
+    // Ключи симуляции выводятся из фиксированных зёрен, чтобы совпадать с
+    // адресами из dev-спецификации
+    let miner_keypair = keypair_from_seed("triad-dev-miner");
     let miner_address = generate_address(&miner_keypair.public);
-    let mut miner_wallet = TokenWallet::new(miner_address);
 
-    let user_keypair = Keypair::generate(&mut rng);
+    let user_keypair = keypair_from_seed("triad-dev-user");
     let user_address = generate_address(&user_keypair.public);
-    let mut user_wallet = TokenWallet::new(user_address);
 
-    let mut economy = TokenEconomy::new(1_000_000, 0.05, 0.01);
-    info!("Blockchain simulation started; total supply: {}", economy.total_supply);
+    {
+        let mut state = app_state.lock().unwrap();
+        if state.chain.is_empty() {
+            let mempool = Arc::clone(&state.mempool);
+            let inserted = tree.insert(spec.genesis_block(), state.chain.as_mut(), &mut mempool.lock().unwrap());
+            if let Err(e) = inserted {
+                error!("Failed to apply genesis block: {}", e);
+            }
+        }
+        let state = &mut *state;
+        if let Err(e) = state.economy.sync(state.chain.as_ref()) {
+            error!("Failed to replay token economy: {}", e);
+        }
+        info!("Blockchain simulation started on {} ({}); total supply: {}", spec.name, spec.consensus.name(), state.economy.total_supply);
+    }
 
-    let mut last_block_time = Utc::now().timestamp();
+    let miner = Miner::default();
 
-    for i in 1..=20 {
-        let previous_hash = {
+    for _ in 0..20 {
+        // Высота и родитель берутся из вершины хранилища, а не из счётчика цикла
+        let (i, previous_hash, ancestors) = {
             let state = app_state.lock().unwrap();
-            if state.blockchain.is_empty() {
-                "0".into()
-            } else {
-                state.blockchain.last().unwrap().hash.clone().unwrap_or_default()
-            }
+            let previous_hash = state.chain.tip_hash().unwrap_or_else(|| "0".into());
+            (state.chain.len() as u64, previous_hash, state.chain.headers().to_vec())
         };
 
-        let timestamp = 1675303065 + (i * 1000);
+        let timestamp = (spec.genesis_timestamp + i * spec.difficulty.target_block_time).max(spec.timestamps.min_timestamp(&ancestors));
 
         let mempool_arc = {
             let state = app_state.lock().unwrap();
             state.mempool.clone()
         };
-        let txs = mempool_arc.lock().unwrap().take_all();
+        let txs = mempool_arc.lock().unwrap().take_for_block(&spec.limits);
         let mut transactions = txs;
 
         if transactions.is_empty() {
             let tx_output = TxOutput {
                 asset: "SOL".into(),
-                recipient: miner_wallet.address.clone(),
+                recipient: miner_address.clone(),
                 amount: 10,
             };
             let mut tx = Transaction::new_cross_chain(
-                user_wallet.address.clone(),
+                user_address.clone(),
                 vec![tx_output],
-                1,
+                spec.monetary.transaction_fee,
                 "Solana".into(),
                 "Ethereum".into(),
             );
             tx.payload = Some("0xContractAddress:transfer:100".into());
             tx.tx_type = TxType::ContractCall;
+            tx.gas_limit = Some(21_000);
+            tx.gas_price = Some(1);
+            tx.chain_id = spec.chain_id;
+            {
+                let state = app_state.lock().unwrap();
+                tx.nonce = state.mempool.lock().unwrap().next_nonce(&user_address, state.chain.state());
+            }
             tx.sign(&user_keypair);
             transactions.push(tx);
         }
@@ -111,62 +171,79 @@ fn main() {
             }
         }
 
-        let mut block = Block::new(i, previous_hash, timestamp, transactions, "Hybrid".into());
+        let mut block = Block::new(i, previous_hash, timestamp, transactions, spec.consensus.name().into());
+        block.header.producer = miner_address.clone();
+        block.header.bits = next_bits_with(&ancestors, &spec.difficulty);
+        block.header.miner_reward = spec.monetary.emission.subsidy(i);
+        block.header.transaction_fee = spec.monetary.transaction_fee;
+        if let Err(e) = block.insert_coinbase_with(spec.chain_id, &spec.monetary.fees) {
+            warn!("Block {} has no valid coinbase, skipping: {}", i, e);
+            continue;
+        }
+        {
+            let state = app_state.lock().unwrap();
+            match state.chain.state().state_root_after(&block) {
+                Ok(root) => block.header.state_root = root,
+                Err(e) => {
+                    warn!("Block {} does not apply to current state, skipping: {}", i, e);
+                    continue;
+                }
+            }
+        }
         block.sign(&miner_keypair);
-        let difficulty = 4 + (i / 2);
-        block.mine(difficulty, last_block_time);
+        // Если за время майнинга вершина сменилась (принят конкурирующий блок),
+        // работа на устаревшем родителе отменяется
+        let cancel = CancellationToken::new();
+        let watch = {
+            let (app_state, parent_hash) = (Arc::clone(&app_state), block.header.parent_hash.clone());
+            StaleWatch::spawn(&cancel, Duration::from_millis(200), move || {
+                app_state.lock().unwrap().chain.tip_hash().unwrap_or_else(|| "0".into()) != parent_hash
+            })
+        };
+        let mined = miner.mine(block, &cancel, |p| info!("Mining block {}: {} attempts, {:.0} H/s", i, p.attempts, p.hashrate));
+        drop(watch);
+        let block = match mined {
+            MineResult::Found { block, .. } => *block,
+            MineResult::Cancelled { attempts } => {
+                warn!("Mining of block {} cancelled after {} attempts", i, attempts);
+                continue;
+            }
+            MineResult::Exhausted { attempts } => {
+                warn!("Search space for block {} exhausted after {} attempts", i, attempts);
+                continue;
+            }
+        };
 
         // Использование плагин-системы для проверки блока
-        let mut plugin_manager = PluginManager::new();
-        // Регистрируем плагины для всех механизмов консенсуса
-        plugin_manager.register_plugin(Box::new(consensus::PoW));
-        plugin_manager.register_plugin(Box::new(consensus::PoS));
-        plugin_manager.register_plugin(Box::new(consensus::DPoS));
-        plugin_manager.register_plugin(Box::new(consensus::Tendermint));
-        plugin_manager.register_plugin(Box::new(consensus::PoSpace));
-
-        if plugin_manager.validate_block(&block) {
+        if plugin_manager.validate_block(&block, &ChainContext { ancestors: &ancestors }) {
             info!("Block {} validated by consensus plugins", i);
-            miner_wallet.add_tokens("TRD", block.miner_reward);
-            economy.total_supply += block.miner_reward;
-            if let Err(e) = user_wallet.subtract_tokens("TRD", block.transaction_fee) {
-                warn!("Fee deduction failed: {}", e);
-            } else {
-                economy.burn_tokens(block.transaction_fee);
-            }
-            {
-                let mut state = app_state.lock().unwrap();
-                if Block::is_unique_hash(&state.blockchain, block.hash.as_ref().unwrap_or(&"".into())) {
-                    state.blockchain.push(block);
-                    info!("Block {} added to blockchain", i);
-                } else {
-                    warn!("Block {} has duplicate hash", i);
+            let mut state = app_state.lock().unwrap();
+            let mempool = Arc::clone(&state.mempool);
+            let inserted = tree.insert(block, state.chain.as_mut(), &mut mempool.lock().unwrap());
+            match inserted {
+                Ok(status) => {
+                    // Учёт идёт по основной цепочке: боковые ветки не выпускают
+                    // монет, а реорганизация откатывает отключённые блоки
+                    let state = &mut *state;
+                    if let Err(e) = state.economy.sync(state.chain.as_ref()) {
+                        warn!("Failed to update token economy: {}", e);
+                    }
+                    info!("Block {} added to block tree: {:?}", i, status);
                 }
+                Err(e) => warn!("{}", e),
             }
         } else {
             warn!("Block {} failed consensus plugin validation", i);
         }
-
-        last_block_time = Utc::now().timestamp();
-        if i % 5 == 0 {
-            economy.apply_inflation();
-        }
     }
 
-    let save_path = "blockchain.json";
     {
         let state = app_state.lock().unwrap();
-        if let Err(e) = Block::save_to_file(&state.blockchain, save_path) {
-            error!("Failed to save blockchain: {}", e);
-        } else {
-            info!("Blockchain saved to {}", save_path);
-        }
+        info!("Miner's balance: {}", state.chain.state().balance(&miner_address, "TRD"));
+        info!("User's balance: {}", state.chain.state().balance(&user_address, "TRD"));
+        info!("Final total supply: {}", state.economy.total_supply);
     }
 
-    info!("Miner's balance: {}", miner_wallet.get_balance("TRD"));
-    info!("User's balance: {}", user_wallet.get_balance("TRD"));
-    info!("Final total supply: {}", economy.total_supply);
-
     {
         let app_state_clone = Arc::clone(&app_state);
         thread::spawn(move || {
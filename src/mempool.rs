@@ -1,5 +1,8 @@
 use serde::{Serialize, Deserialize};
-use crate::transaction::Transaction;
+use crate::transaction::{Transaction, TxInput};
+use crate::utxo::UtxoSet;
+use crate::state::State;
+use crate::block_limits::BlockLimits;
 
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Mempool {
@@ -7,13 +10,120 @@ pub struct Mempool {
 }
 
 impl Mempool {
-    pub fn add_transaction(&mut self, tx: Transaction) {
+    /// Принимает транзакцию, если она подписана ключом отправителя, её входы
+    /// есть в UTXO-наборе цепочки и не потрачены другой транзакцией, уже
+    /// ожидающей в пуле, nonce продолжает последовательность отправителя, а
+    /// свободного баланса хватает на неё вместе с его ожидающими транзакциями.
+    pub fn add_transaction(&mut self, tx: Transaction, utxos: &UtxoSet, state: &State) -> Result<(), String> {
+        if tx.is_coinbase() {
+            return Err("Coinbase transactions are created by block producers only".into());
+        }
+        if !tx.verify_signer() {
+            return Err(format!("Transaction is not signed by the key of {}", tx.sender));
+        }
+        tx.validate(utxos)?;
+        state.check_replay(&tx, self.next_nonce(&tx.sender, state))?;
+        state.assets.check(&tx)?;
+        self.check_balance(&tx, state)?;
+        if let Some(input) = tx.inputs.iter().find(|input| self.spends(input)) {
+            return Err(format!("Input {}:{} is already spent by a pending transaction", input.tx_id, input.output_index));
+        }
         self.transactions.push(tx);
+        Ok(())
     }
-    
+
+    /// Следующий nonce отправителя: подтверждённый в состоянии плюс ожидающие в пуле.
+    pub fn next_nonce(&self, sender: &str, state: &State) -> u64 {
+        let pending = self.transactions.iter().filter(|tx| tx.sender == sender).count() as u64;
+        state.next_nonce(sender) + pending
+    }
+
+    fn check_balance(&self, tx: &Transaction, state: &State) -> Result<(), String> {
+        let pending = self.transactions.iter().filter(|p| p.sender == tx.sender);
+        for (asset, amount) in tx.debits()? {
+            let mut required = amount;
+            for pending_tx in pending.clone() {
+                let debit = pending_tx.debits()?.get(asset).copied().unwrap_or(0);
+                required = required.checked_add(debit).ok_or_else(|| format!("Pending {} of {} overflows u64", asset, tx.sender))?;
+            }
+            let spendable = state.spendable_balance(&tx.sender, asset);
+            if spendable < required {
+                return Err(format!("Insufficient spendable {} for {}: {} available, {} required with pending transactions", asset, tx.sender, spendable, required));
+            }
+        }
+        Ok(())
+    }
+
+    fn spends(&self, input: &TxInput) -> bool {
+        self.transactions.iter().any(|tx| tx.inputs.contains(input))
+    }
+
+    /// Перепроверяет пул после смены вершины цепочки: возвращает в него
+    /// транзакции из отключённых блоков и выбрасывает то, что уже попало в
+    /// цепочку или стало недействительным. Возвращает число отброшенных транзакций.
+    pub fn revalidate(&mut self, orphaned: Vec<Transaction>, utxos: &UtxoSet, state: &State) -> usize {
+        let mut candidates = orphaned;
+        candidates.append(&mut self.transactions);
+        candidates.sort_by_key(|tx| tx.nonce);
+        let total = candidates.len();
+        for tx in candidates {
+            let _ = self.add_transaction(tx, utxos, state);
+        }
+        total - self.transactions.len()
+    }
+
+    /// Забирает из пула транзакции для следующего блока в пределах лимитов;
+    /// остальные ждут следующего блока.
+    pub fn take_for_block(&mut self, limits: &BlockLimits) -> Vec<Transaction> {
+        let (selected, remaining) = limits.select(self.take_all());
+        self.transactions = remaining;
+        selected
+    }
+
     pub fn take_all(&mut self) -> Vec<Transaction> {
         let txs = self.transactions.clone();
         self.transactions.clear();
         txs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::transaction::TxOutput;
+    use crate::wallet::{generate_address, keypair_from_seed};
+
+    fn funded_state(address: &str) -> State {
+        let output = TxOutput { asset: "TRD".into(), recipient: address.into(), amount: 100 };
+        let mut genesis = Block::new(0, "0".into(), 0, vec![Transaction::new("genesis".into(), vec![output], 0)], "Hybrid".into());
+        genesis.header.state_root = State::default().state_root_after(&genesis).unwrap();
+        State::from_blocks(State::default(), &[genesis]).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_unsigned_and_underfunded_transactions() {
+        let alice = keypair_from_seed("alice");
+        let address = generate_address(&alice.public);
+        let state = funded_state(&address);
+        let utxos = UtxoSet::default();
+        let transfer = |amount, fee, nonce| {
+            let mut tx = Transaction::new(address.clone(), vec![TxOutput { asset: "TRD".into(), recipient: "bob".into(), amount }], fee);
+            tx.nonce = nonce;
+            tx
+        };
+        let mut mempool = Mempool::default();
+
+        assert!(mempool.add_transaction(transfer(10, 1, 0), &utxos, &state).is_err(), "Unsigned transactions must be rejected");
+        let mut tx = transfer(60, 1, 0);
+        tx.sign(&alice);
+        mempool.add_transaction(tx, &utxos, &state).unwrap();
+        let mut tx = transfer(40, 1, 1);
+        tx.sign(&alice);
+        assert!(mempool.add_transaction(tx, &utxos, &state).is_err(), "Pending transactions count against the balance");
+        let mut tx = transfer(1, u64::MAX, 1);
+        tx.sign(&alice);
+        assert!(mempool.add_transaction(tx, &utxos, &state).is_err(), "Overflowing totals must be rejected");
+        assert_eq!(mempool.next_nonce(&address, &state), 1);
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Serialize, Deserialize};
+use crate::encoding::{tag, to_hex, Encoder};
+use crate::transaction::Transaction;
+
+/// Положение соседнего узла относительно текущего на уровне дерева.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub side: Side,
+    pub hash: String,
+}
+
+/// Доказательство включения: соседние узлы от листа к корню.
+/// Уровни, на которых узел остался без пары, в доказательстве пропускаются.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    pub steps: Vec<ProofStep>,
+}
+
+fn leaf_hash(tx_id: &str) -> [u8; 32] {
+    let mut enc = Encoder::new(tag::MERKLE_LEAF);
+    enc.put_str(tx_id);
+    enc.hash()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut enc = Encoder::new(tag::MERKLE_NODE);
+    enc.put_bytes(left).put_bytes(right);
+    enc.hash()
+}
+
+/// Следующий уровень дерева. Непарный последний узел поднимается без изменений,
+/// а не дублируется: иначе списки [a, b, c] и [a, b, c, c] дают один корень (CVE-2012-2459).
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2).map(|pair| match pair {
+        [left, right] => node_hash(left, right),
+        [single] => *single,
+        _ => unreachable!(),
+    }).collect()
+}
+
+pub fn calculate_merkle_root(transactions: &[Transaction]) -> String {
+    if transactions.is_empty() {
+        return Encoder::new(tag::MERKLE_LEAF).hash_hex();
+    }
+    let mut level: Vec<[u8; 32]> = transactions.iter().map(|tx| leaf_hash(&tx.id)).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    to_hex(&level[0])
+}
+
+pub fn merkle_proof(transactions: &[Transaction], tx_id: &str) -> Option<MerkleProof> {
+    let leaf_index = transactions.iter().position(|tx| tx.id == tx_id)?;
+    let mut level: Vec<[u8; 32]> = transactions.iter().map(|tx| leaf_hash(&tx.id)).collect();
+    let mut index = leaf_index;
+    let mut steps = Vec::new();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            let side = if sibling < index { Side::Left } else { Side::Right };
+            steps.push(ProofStep { side, hash: to_hex(&level[sibling]) });
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    Some(MerkleProof { leaf_index: leaf_index as u64, leaf_count: transactions.len() as u64, steps })
+}
+
+fn decode_hash(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+/// Проверяет, что транзакция `tx_id` входит в дерево с корнем `root`.
+/// Стороны соседей сверяются с позицией листа, поэтому доказательство
+/// нельзя переставить под другой индекс.
+pub fn verify_merkle_proof(root: &str, tx_id: &str, proof: &MerkleProof) -> bool {
+    if proof.leaf_index >= proof.leaf_count {
+        return false;
+    }
+    let mut hash = leaf_hash(tx_id);
+    let mut index = proof.leaf_index;
+    let mut width = proof.leaf_count;
+    let mut steps = proof.steps.iter();
+    while width > 1 {
+        let sibling = index ^ 1;
+        if sibling < width {
+            let step = match steps.next() {
+                Some(step) => step,
+                None => return false,
+            };
+            let expected_side = if sibling < index { Side::Left } else { Side::Right };
+            let sibling_hash = match decode_hash(&step.hash) {
+                Some(h) if step.side == expected_side => h,
+                _ => return false,
+            };
+            hash = match step.side {
+                Side::Left => node_hash(&sibling_hash, &hash),
+                Side::Right => node_hash(&hash, &sibling_hash),
+            };
+        }
+        index /= 2;
+        width = width.div_ceil(2);
+    }
+    steps.next().is_none() && to_hex(&hash) == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TxOutput;
+
+    fn transactions(count: u64) -> Vec<Transaction> {
+        (0..count).map(|i| {
+            let output = TxOutput { asset: "TRD".into(), recipient: format!("r{}", i), amount: i + 1 };
+            Transaction::new("sender".into(), vec![output], 1)
+        }).collect()
+    }
+
+    #[test]
+    fn test_proofs_verify_for_every_leaf() {
+        for count in 1..=7 {
+            let txs = transactions(count);
+            let root = calculate_merkle_root(&txs);
+            for tx in &txs {
+                let proof = merkle_proof(&txs, &tx.id).unwrap();
+                assert!(verify_merkle_proof(&root, &tx.id, &proof), "{} leaves, tx {}", count, tx.id);
+                assert!(!verify_merkle_proof(&root, "not-a-transaction", &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicated_last_leaf_changes_root() {
+        let txs = transactions(3);
+        let mut padded = txs.clone();
+        padded.push(txs[2].clone());
+        assert_ne!(calculate_merkle_root(&txs), calculate_merkle_root(&padded));
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
+use crate::block::{Block, BlockHeader};
+use crate::target::{meets_target, U256};
+
+/// Флаг отмены майнинга, разделяемый между потоками (например, при получении
+/// конкурирующего блока).
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Фоновая проверка устаревания работы майнера: раз в `interval` вызывает
+/// `is_stale` и отменяет токен, как только та вернёт true — например, когда
+/// принят конкурирующий блок и вершина цепочки ушла с родителя. Поток
+/// останавливается при drop.
+pub struct StaleWatch {
+    stop: CancellationToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StaleWatch {
+    pub fn spawn<F: Fn() -> bool + Send + 'static>(cancel: &CancellationToken, interval: Duration, is_stale: F) -> Self {
+        let stop = CancellationToken::new();
+        let handle = {
+            let (stop, cancel) = (stop.clone(), cancel.clone());
+            thread::spawn(move || {
+                while !stop.is_cancelled() && !cancel.is_cancelled() {
+                    if is_stale() {
+                        cancel.cancel();
+                        break;
+                    }
+                    thread::sleep(interval);
+                }
+            })
+        };
+        StaleWatch { stop, handle: Some(handle) }
+    }
+}
+
+impl Drop for StaleWatch {
+    fn drop(&mut self) {
+        self.stop.cancel();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MiningProgress {
+    pub attempts: u64,
+    pub elapsed: Duration,
+    /// Хешей в секунду с начала майнинга.
+    pub hashrate: f64,
+}
+
+pub enum MineResult {
+    Found { block: Box<Block>, attempts: u64 },
+    Cancelled { attempts: u64 },
+    Exhausted { attempts: u64 },
+}
+
+/// Параметры майнера. Nonce перебирается пакетами; между пакетами проверяется
+/// отмена и при необходимости отправляется отчёт о прогрессе. Когда диапазон
+/// nonce исчерпан, увеличивается `extra_nonce` заголовка (он не входит в подпись
+/// производителя), и перебор начинается заново.
+pub struct Miner {
+    batch_size: u64,
+    pub report_interval: Duration,
+    pub max_nonce: u64,
+    pub max_extra_nonce: u64,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Miner {
+            batch_size: 1 << 16,
+            report_interval: Duration::from_secs(5),
+            max_nonce: u64::MAX,
+            max_extra_nonce: u64::MAX,
+        }
+    }
+}
+
+impl Miner {
+    /// Майнер с заданным размером пакета; пустой пакет не имеет смысла.
+    pub fn new(batch_size: u64) -> Result<Self, String> {
+        if batch_size == 0 {
+            return Err("Miner batch size must be positive".into());
+        }
+        Ok(Miner { batch_size, ..Miner::default() })
+    }
+
+    pub fn batch_size(&self) -> u64 {
+        self.batch_size
+    }
+
+    /// Майнит блок под цель `block.header.bits`.
+    pub fn mine<F: FnMut(&MiningProgress)>(&self, mut block: Block, cancel: &CancellationToken, mut on_progress: F) -> MineResult {
+        let target = match U256::from_compact(block.header.bits) {
+            Some(target) => target,
+            None => return MineResult::Exhausted { attempts: 0 },
+        };
+        let started = Instant::now();
+        let mut last_report = started;
+        let mut attempts = 0u64;
+
+        loop {
+            let prefix = block.header.hash_prefix();
+            let mut start = 0u64;
+            loop {
+                if cancel.is_cancelled() {
+                    return MineResult::Cancelled { attempts };
+                }
+                let end = start.saturating_add(self.batch_size - 1).min(self.max_nonce);
+                let found = (start..=end).into_par_iter().find_first(|&nonce| {
+                    meets_target(&BlockHeader::hash_with_nonce(&prefix, nonce), &target)
+                });
+                if let Some(nonce) = found {
+                    block.header.nonce = nonce;
+                    block.hash = Some(block.header.hash());
+                    return MineResult::Found { block: Box::new(block), attempts: attempts + nonce - start + 1 };
+                }
+                attempts += end - start + 1;
+
+                if last_report.elapsed() >= self.report_interval {
+                    last_report = Instant::now();
+                    let elapsed = started.elapsed();
+                    let hashrate = attempts as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+                    on_progress(&MiningProgress { attempts, elapsed, hashrate });
+                }
+                if end == self.max_nonce {
+                    break;
+                }
+                start = end + 1;
+            }
+            if block.header.extra_nonce >= self.max_extra_nonce {
+                return MineResult::Exhausted { attempts };
+            }
+            block.header.extra_nonce += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::POW_LIMIT_BITS;
+
+    fn block(bits: u32) -> Block {
+        let mut block = Block::new(1, "0".into(), 1675303065, Vec::new(), "Hybrid".into());
+        block.header.bits = bits;
+        block
+    }
+
+    #[test]
+    fn test_found_block_meets_target() {
+        match Miner::default().mine(block(POW_LIMIT_BITS), &CancellationToken::new(), |_| {}) {
+            MineResult::Found { block: mined, .. } => assert!(mined.verify_hash() && mined.header.meets_target()),
+            _ => panic!("Block should be mined at the pow limit"),
+        }
+    }
+
+    #[test]
+    fn test_cancelled_and_exhausted() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(Miner::default().mine(block(POW_LIMIT_BITS), &cancel, |_| {}), MineResult::Cancelled { .. }));
+
+        let miner = Miner { batch_size: 16, max_nonce: 63, max_extra_nonce: 2, ..Miner::default() };
+        match miner.mine(block(0x0100_0001), &CancellationToken::new(), |_| {}) {
+            MineResult::Exhausted { attempts } => assert_eq!(attempts, 64 * 3),
+            _ => panic!("Impossible target must exhaust the search space"),
+        }
+        assert!(Miner::new(0).is_err());
+    }
+
+    #[test]
+    fn test_stale_watch_cancels_mining() {
+        let stale = Arc::new(AtomicBool::new(false));
+        let cancel = CancellationToken::new();
+        let watch = {
+            let stale = Arc::clone(&stale);
+            StaleWatch::spawn(&cancel, Duration::from_millis(1), move || stale.load(Ordering::SeqCst))
+        };
+        stale.store(true, Ordering::SeqCst);
+        // Цель недостижима: майнинг завершится только отменой
+        let miner = Miner { batch_size: 1 << 10, ..Miner::default() };
+        assert!(matches!(miner.mine(block(0x0100_0001), &cancel, |_| {}), MineResult::Cancelled { .. }));
+        drop(watch);
+    }
+}
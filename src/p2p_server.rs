@@ -1,12 +1,11 @@
 use std::net::{TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use crate::mempool::Mempool;
+use crate::rest_api::AppState;
 use log::{info, error};
 use std::io::{Read, Write};
-use serde_json;
 
-fn handle_client(mut stream: TcpStream, mempool: Arc<Mutex<Mempool>>) {
+fn handle_client(mut stream: TcpStream, app_state: Arc<Mutex<AppState>>) {
     let mut buffer = Vec::new();
     if let Err(e) = stream.read_to_end(&mut buffer) {
         error!("Error reading stream: {}", e);
@@ -15,8 +14,15 @@ fn handle_client(mut stream: TcpStream, mempool: Arc<Mutex<Mempool>>) {
     match serde_json::from_slice::<crate::transaction::Transaction>(&buffer) {
         Ok(tx) => {
             info!("Received transaction: {:?}", tx);
-            mempool.lock().unwrap().add_transaction(tx);
-            let _ = stream.write_all(b"Transaction added\n");
+            let state = app_state.lock().unwrap();
+            let result = state.mempool.lock().unwrap().add_transaction(tx, state.chain.utxos(), state.chain.state());
+            match result {
+                Ok(()) => { let _ = stream.write_all(b"Transaction added\n"); },
+                Err(e) => {
+                    error!("Transaction rejected: {}", e);
+                    let _ = stream.write_all(format!("Transaction rejected: {}\n", e).as_bytes());
+                }
+            }
         },
         Err(e) => {
             error!("Failed to parse transaction: {}", e);
@@ -25,15 +31,15 @@ fn handle_client(mut stream: TcpStream, mempool: Arc<Mutex<Mempool>>) {
     }
 }
 
-pub fn start_p2p_server(mempool: Arc<Mutex<Mempool>>) {
+pub fn start_p2p_server(app_state: Arc<Mutex<AppState>>) {
     let listener = TcpListener::bind("0.0.0.0:7000").expect("Failed to bind");
     info!("P2P server started on port 7000");
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                let pool = Arc::clone(&mempool);
+                let state = Arc::clone(&app_state);
                 thread::spawn(move || {
-                    handle_client(stream, pool);
+                    handle_client(stream, state);
                 });
             },
             Err(e) => error!("Connection error: {}", e),
@@ -1,35 +1,160 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use serde::Deserialize;
 use serde_json::json;
 use std::sync::{Arc, Mutex};
 use crate::block::Block;
+use crate::chain_spec::ChainSpec;
+use crate::chain_store::ChainStore;
+use crate::difficulty::chain_work;
 use crate::mempool::Mempool;
+use crate::supply_audit::{audit, audit_state};
+use crate::token_economy::TokenEconomy;
 
 
-#[derive(serde::Serialize, serde::Deserialize, Default)]
 pub struct AppState {
-    pub blockchain: Vec<Block>,
-    #[serde(skip)]
+    pub spec: ChainSpec,
+    pub chain: Box<dyn ChainStore>,
     pub mempool: Arc<Mutex<Mempool>>,
+    pub economy: TokenEconomy,
 }
 
 pub async fn get_blocks(data: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
     let state = data.lock().unwrap();
-    HttpResponse::Ok().json(&state.blockchain)
+    match state.chain.blocks() {
+        Ok(blocks) => HttpResponse::Ok().json(blocks),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": e})),
+    }
+}
+
+pub async fn get_headers(data: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
+    let state = data.lock().unwrap();
+    HttpResponse::Ok().json(state.chain.headers())
 }
 
 pub async fn get_status(data: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
     let state = data.lock().unwrap();
     let status = json!({
-        "block_count": state.blockchain.len(),
-        "last_block_hash": state.blockchain.last().and_then(|b| b.hash.clone()).unwrap_or_default(),
+        "block_count": state.chain.len(),
+        "last_block_hash": state.chain.tip_hash().unwrap_or_default(),
+        "chain_work": chain_work(state.chain.headers()).to_string(),
     });
     HttpResponse::Ok().json(status)
 }
 
 pub async fn add_transaction(data: web::Data<Arc<Mutex<AppState>>>, new_tx: web::Json<crate::transaction::Transaction>) -> impl Responder {
     let state = data.lock().unwrap();
-    state.mempool.lock().unwrap().add_transaction(new_tx.into_inner());
-    HttpResponse::Ok().json(json!({"status": "transaction added"}))
+    let result = state.mempool.lock().unwrap().add_transaction(new_tx.into_inner(), state.chain.utxos(), state.chain.state());
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({"status": "transaction added"})),
+        Err(e) => HttpResponse::BadRequest().json(json!({"status": "transaction rejected", "error": e})),
+    }
+}
+
+pub async fn get_next_nonce(data: web::Data<Arc<Mutex<AppState>>>, address: web::Path<String>) -> impl Responder {
+    let state = data.lock().unwrap();
+    let next_nonce = state.mempool.lock().unwrap().next_nonce(&address, state.chain.state());
+    HttpResponse::Ok().json(json!({
+        "address": address.into_inner(),
+        "chain_id": state.chain.state().chain_id,
+        "next_nonce": next_nonce,
+    }))
+}
+
+/// Балансы адреса по активам: полный, заблокированный вестингом и доступный.
+pub async fn get_balance(data: web::Data<Arc<Mutex<AppState>>>, address: web::Path<String>) -> impl Responder {
+    let state = data.lock().unwrap();
+    let ledger = state.chain.state();
+    let account = match ledger.account(&address) {
+        Some(account) => account,
+        None => return HttpResponse::NotFound().json(json!({"error": "unknown address"})),
+    };
+    let balances: serde_json::Map<String, serde_json::Value> = account.wallet.balances.keys()
+        .map(|asset| (asset.clone(), json!({
+            "total": ledger.balance(&address, asset),
+            "locked": ledger.locked_balance(&address, asset),
+            "spendable": ledger.spendable_balance(&address, asset),
+        })))
+        .collect();
+    let staked = ledger.staking.bonded_by(&address);
+    HttpResponse::Ok().json(json!({
+        "address": address.into_inner(),
+        "height": ledger.height,
+        "balances": balances,
+        "staked": staked,
+    }))
+}
+
+/// Таблица стейков: валидаторы, общий стейк и выводы в периоде анбондинга.
+pub async fn get_staking(data: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
+    let state = data.lock().unwrap();
+    let staking = &state.chain.state().staking;
+    let validators: serde_json::Map<String, serde_json::Value> = staking.validators()
+        .map(|(validator, stake)| (validator.clone(), json!(stake)))
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "total_bonded": staking.total_bonded(),
+        "validators": validators,
+        "unbonding": staking.unbonding(),
+    }))
+}
+
+pub async fn get_merkle_proof(data: web::Data<Arc<Mutex<AppState>>>, tx_id: web::Path<String>) -> impl Responder {
+    let state = data.lock().unwrap();
+    let block = match state.chain.locate_transaction(&tx_id) {
+        Some(location) => state.chain.get_by_height(location.height),
+        None => Ok(None),
+    };
+    let found = match block {
+        Ok(block) => block.and_then(|block| block.merkle_proof(&tx_id).map(|proof| (block, proof))),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({"error": e})),
+    };
+    match found {
+        Some((block, proof)) => HttpResponse::Ok().json(json!({
+            "tx_id": tx_id.into_inner(),
+            "block_index": block.header.height,
+            "block_hash": block.calculate_hash(),
+            "merkle_root": block.header.merkle_root,
+            "proof": proof,
+        })),
+        None => HttpResponse::NotFound().json(json!({"error": "transaction not found"})),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SupplyQuery {
+    pub height: Option<u64>,
+}
+
+/// Блоки основной цепочки от генезиса до `height`. Блокировка берётся на
+/// каждый блок отдельно, чтобы чтение с диска не останавливало узел.
+fn blocks_until(data: &Mutex<AppState>, height: u64) -> Result<Vec<Block>, String> {
+    (0..=height)
+        .map(|h| data.lock().unwrap().chain.get_by_height(h)?.ok_or(format!("Height {} is above the chain tip", height)))
+        .collect()
+}
+
+/// Аудит предложения на высоте `?height=` (по умолчанию — на вершине цепочки).
+/// Под блокировкой снимается только копия учёта и состояния вершины; прошлые
+/// высоты воспроизводятся уже без неё.
+pub async fn get_supply(data: web::Data<Arc<Mutex<AppState>>>, query: web::Query<SupplyQuery>) -> impl Responder {
+    let (spec, economy, tip_state, height) = {
+        let state = data.lock().unwrap();
+        let tip = state.chain.best_tip().map(|h| h.height);
+        let height = match query.height.or(tip) {
+            Some(height) => height,
+            None => return HttpResponse::NotFound().json(json!({"error": "chain is empty"})),
+        };
+        let tip_state = (Some(height) == tip).then(|| state.chain.state().clone());
+        (state.spec.clone(), state.economy.clone(), tip_state, height)
+    };
+    let report = match tip_state {
+        Some(tip_state) => audit_state(&spec, &tip_state, &economy),
+        None => blocks_until(&data, height).and_then(|blocks| audit(&spec, &blocks, height, &economy)),
+    };
+    match report {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::BadRequest().json(json!({"error": e})),
+    }
 }
 
 pub fn start_rest_server(app_state: Arc<Mutex<AppState>>) {
@@ -39,8 +164,14 @@ pub fn start_rest_server(app_state: Arc<Mutex<AppState>>) {
             App::new()
                 .app_data(web::Data::new(Arc::clone(&app_state)))
                 .route("/blocks", web::get().to(get_blocks))
+                .route("/headers", web::get().to(get_headers))
                 .route("/status", web::get().to(get_status))
                 .route("/transaction", web::post().to(add_transaction))
+                .route("/nonce/{address}", web::get().to(get_next_nonce))
+                .route("/balance/{address}", web::get().to(get_balance))
+                .route("/staking", web::get().to(get_staking))
+                .route("/proof/{tx_id}", web::get().to(get_merkle_proof))
+                .route("/supply", web::get().to(get_supply))
         })
         .bind("127.0.0.1:8080")
         .expect("Failed to bind REST server")
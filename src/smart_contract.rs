@@ -5,6 +5,7 @@ pub trait SmartContract {
     fn execute(&mut self, input: &str) -> Result<String, String>;
 }
 
+#[derive(Default)]
 pub struct ContractManager {
     pub contracts: HashMap<String, Box<dyn SmartContract>>,
 }
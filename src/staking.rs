@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::encoding::{Encode, Encoder};
+
+/// Период анбондинга, если спецификация сети его не задаёт.
+pub const DEFAULT_UNBONDING_PERIOD: u64 = 100;
+
+/// Параметры стейкинга из спецификации сети.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StakingParams {
+    /// Сколько блоков средства остаются заблокированными после Unstake.
+    pub unbonding_period: u64,
+}
+
+impl Default for StakingParams {
+    fn default() -> Self {
+        StakingParams { unbonding_period: DEFAULT_UNBONDING_PERIOD }
+    }
+}
+
+impl StakingParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.unbonding_period == 0 {
+            return Err("Unbonding period must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+/// Средства, выведенные из стейка и ожидающие освобождения.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unbonding {
+    pub delegator: String,
+    pub validator: String,
+    pub amount: u64,
+    /// Высота, начиная с которой сумма возвращается на баланс делегатора.
+    pub release_height: u64,
+}
+
+impl Encode for Unbonding {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_str(&self.delegator).put_str(&self.validator).put_u64(self.amount).put_u64(self.release_height);
+    }
+}
+
+/// Таблица стейков: сколько TRD каждый делегатор привязал к каждому
+/// валидатору, и очередь выводов, ждущих окончания периода анбондинга.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StakingLedger {
+    /// Валидатор → делегатор → сумма.
+    bonds: BTreeMap<String, BTreeMap<String, u64>>,
+    unbonding: Vec<Unbonding>,
+}
+
+impl StakingLedger {
+    pub fn bond(&mut self, validator: &str, delegator: &str, amount: u64) {
+        *self.bonds.entry(validator.to_string()).or_default().entry(delegator.to_string()).or_insert(0) += amount;
+    }
+
+    /// Выводит `amount` из стейка делегатора у валидатора; средства
+    /// освобождаются через `period` блоков после `height`.
+    pub fn unbond(&mut self, validator: &str, delegator: &str, amount: u64, height: u64, period: u64) -> Result<(), String> {
+        let release_height = height.checked_add(period)
+            .ok_or(format!("Release height of unbonding at {} overflows u64", height))?;
+        let delegations = self.bonds.get_mut(validator);
+        let bonded = delegations.as_ref().and_then(|d| d.get(delegator)).copied().unwrap_or(0);
+        if bonded < amount {
+            return Err(format!("{} has {} bonded to {}, cannot unbond {}", delegator, bonded, validator, amount));
+        }
+        if let Some(delegations) = delegations {
+            if bonded == amount {
+                delegations.remove(delegator);
+            } else {
+                delegations.insert(delegator.to_string(), bonded - amount);
+            }
+            if delegations.is_empty() {
+                self.bonds.remove(validator);
+            }
+        }
+        self.unbonding.push(Unbonding {
+            delegator: delegator.to_string(),
+            validator: validator.to_string(),
+            amount,
+            release_height,
+        });
+        Ok(())
+    }
+
+    /// Забирает из очереди выводы, срок которых наступил к высоте `height`.
+    pub fn release(&mut self, height: u64) -> Vec<Unbonding> {
+        let (released, pending) = std::mem::take(&mut self.unbonding).into_iter()
+            .partition(|u| u.release_height <= height);
+        self.unbonding = pending;
+        released
+    }
+
+    pub fn validator_stake(&self, validator: &str) -> u64 {
+        self.bonds.get(validator).map_or(0, |d| d.values().sum())
+    }
+
+    /// Валидаторы с ненулевым стейком и их суммарный стейк, по адресу.
+    pub fn validators(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.bonds.iter().map(|(validator, delegations)| (validator, delegations.values().sum()))
+    }
+
+    pub fn total_bonded(&self) -> u64 {
+        self.validators().map(|(_, stake)| stake).sum()
+    }
+
+    /// Сколько делегатор держит в стейке у всех валидаторов.
+    pub fn bonded_by(&self, delegator: &str) -> u64 {
+        self.bonds.values().filter_map(|d| d.get(delegator)).sum()
+    }
+
+    pub fn unbonding(&self) -> &[Unbonding] {
+        &self.unbonding
+    }
+
+    /// Каноническое кодирование для корня состояния.
+    pub fn encode(&self, enc: &mut Encoder) {
+        enc.put_u64(self.bonds.len() as u64);
+        for (validator, delegations) in &self.bonds {
+            enc.put_str(validator).put_u64(delegations.len() as u64);
+            for (delegator, amount) in delegations {
+                enc.put_str(delegator).put_u64(*amount);
+            }
+        }
+        enc.put_list(&self.unbonding);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bond_unbond_and_release() {
+        let mut ledger = StakingLedger::default();
+        ledger.bond("val", "val", 100);
+        ledger.bond("val", "alice", 50);
+        ledger.bond("other", "alice", 10);
+        assert_eq!((ledger.validator_stake("val"), ledger.total_bonded(), ledger.bonded_by("alice")), (150, 160, 60));
+
+        assert!(ledger.unbond("val", "alice", 51, 5, 100).is_err());
+        ledger.unbond("val", "alice", 50, 5, 100).unwrap();
+        ledger.unbond("other", "alice", 10, 7, 100).unwrap();
+        assert_eq!(ledger.validators().collect::<Vec<_>>(), vec![(&"val".to_string(), 100)]);
+        assert!(ledger.unbond("val", "val", 1, u64::MAX, 100).is_err(), "Release height must not overflow");
+        assert_eq!(ledger.validator_stake("val"), 100);
+
+        assert!(ledger.release(5 + 100 - 1).is_empty(), "Funds stay locked for the unbonding period");
+        let released = ledger.release(5 + 100);
+        assert_eq!((released.len(), released[0].amount), (1, 50));
+        assert_eq!(ledger.unbonding().len(), 1);
+    }
+}
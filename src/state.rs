@@ -0,0 +1,648 @@
+use std::collections::BTreeMap;
+use std::io;
+use serde::{Serialize, Deserialize};
+use crate::asset_registry::AssetRegistry;
+use crate::block::Block;
+use crate::encoding::{tag, Encoder};
+use crate::staking::{StakingLedger, StakingParams};
+use crate::token_economy::NATIVE_ASSET;
+use crate::transaction::{Transaction, TxOutput, TxType, DEFAULT_CHAIN_ID};
+use crate::utxo::UtxoSet;
+use crate::vesting::VestingLock;
+use crate::wallet::{is_valid_address, TokenWallet};
+
+/// Состояние одного адреса: балансы по активам, счётчик транзакций и
+/// блокировки вестинга на части баланса. Стейки хранятся в `State::staking`.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub wallet: TokenWallet,
+    pub nonce: u64,
+    #[serde(default)]
+    pub vesting: Vec<VestingLock>,
+}
+
+impl Account {
+    /// Часть баланса актива, ещё не разблокированная на высоте `height`.
+    pub fn locked(&self, asset: &str, height: u64) -> u64 {
+        self.vesting.iter().filter(|lock| lock.asset == asset).map(|lock| lock.locked(height)).sum()
+    }
+
+    pub fn spendable(&self, asset: &str, height: u64) -> u64 {
+        self.wallet.get_balance(asset).saturating_sub(self.locked(asset, height))
+    }
+
+    /// Списывает только разблокированные токены.
+    fn debit(&mut self, asset: &str, amount: u64, height: u64) -> Result<(), String> {
+        let spendable = self.spendable(asset, height);
+        if spendable < amount {
+            return Err(format!("Insufficient spendable {}: {} available, {} requested, {} still vesting", asset, spendable, amount, self.locked(asset, height)));
+        }
+        self.wallet.subtract_tokens(asset, amount)
+    }
+}
+
+/// Мировое состояние, выводимое исключительно из применённых блоков.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct State {
+    pub chain_id: u64,
+    pub accounts: BTreeMap<String, Account>,
+    /// Сожжённое количество по активам.
+    #[serde(default)]
+    pub burned: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub assets: AssetRegistry,
+    #[serde(default)]
+    pub staking: StakingLedger,
+    /// Те же балансы в виде выходов; в корень не входит, так как суммы уже
+    /// зафиксированы балансами аккаунтов.
+    #[serde(default)]
+    pub utxos: UtxoSet,
+    /// Параметры сети; в корень состояния не входят.
+    #[serde(default)]
+    pub staking_params: StakingParams,
+    pub height: Option<u64>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::new(DEFAULT_CHAIN_ID)
+    }
+}
+
+impl State {
+    pub fn new(chain_id: u64) -> Self {
+        State {
+            chain_id,
+            accounts: BTreeMap::new(),
+            burned: BTreeMap::new(),
+            assets: AssetRegistry::default(),
+            staking: StakingLedger::default(),
+            utxos: UtxoSet::default(),
+            staking_params: StakingParams::default(),
+            height: None,
+        }
+    }
+
+    pub fn with_staking_params(mut self, params: StakingParams) -> Self {
+        self.staking_params = params;
+        self
+    }
+
+    /// Пустое состояние той же сети: без блоков, но с её идентификатором и параметрами.
+    pub fn empty(&self) -> State {
+        State::new(self.chain_id).with_staking_params(self.staking_params.clone())
+    }
+
+    /// Восстанавливает состояние, последовательно применяя блоки начиная с
+    /// генезиса к `initial` — начальному состоянию сети из её спецификации.
+    pub fn from_blocks(initial: State, blocks: &[Block]) -> Result<State, String> {
+        let mut state = initial;
+        for block in blocks {
+            state.apply_block(block)?;
+        }
+        Ok(state)
+    }
+
+    pub fn load_from_file(path: &str, initial: State) -> io::Result<State> {
+        let blocks = Block::load_from_file(path)?;
+        State::from_blocks(initial, &blocks).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn account(&self, address: &str) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    /// Полный баланс, включая ещё не разблокированную часть.
+    pub fn balance(&self, address: &str, asset: &str) -> u64 {
+        self.account(address).map(|a| a.wallet.get_balance(asset)).unwrap_or(0)
+    }
+
+    /// Заблокированная вестингом часть баланса на высоте состояния.
+    pub fn locked_balance(&self, address: &str, asset: &str) -> u64 {
+        self.account(address).map(|a| a.locked(asset, self.height.unwrap_or(0))).unwrap_or(0)
+    }
+
+    pub fn spendable_balance(&self, address: &str, asset: &str) -> u64 {
+        self.account(address).map(|a| a.spendable(asset, self.height.unwrap_or(0))).unwrap_or(0)
+    }
+
+    /// Nonce, который должна нести следующая транзакция отправителя.
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        self.account(address).map(|a| a.nonce).unwrap_or(0)
+    }
+
+    /// Проверки защиты от повтора: транзакция из этой сети и с ожидаемым nonce.
+    pub fn check_replay(&self, tx: &Transaction, expected_nonce: u64) -> Result<(), String> {
+        if tx.chain_id != self.chain_id {
+            return Err(format!("Transaction is for chain {}, this is chain {}", tx.chain_id, self.chain_id));
+        }
+        if tx.nonce != expected_nonce {
+            return Err(format!("Invalid nonce for {}: expected {}, got {}", tx.sender, expected_nonce, tx.nonce));
+        }
+        Ok(())
+    }
+
+    fn account_mut(&mut self, address: &str) -> &mut Account {
+        self.accounts.entry(address.to_string()).or_insert_with(|| Account {
+            wallet: TokenWallet::new(address.to_string()),
+            ..Account::default()
+        })
+    }
+
+    /// Корень состояния: хеш канонического кодирования всех аккаунтов,
+    /// упорядоченных по адресу (балансы — по активу), сожжённых сумм, реестра
+    /// активов и таблицы стейков.
+    pub fn root(&self) -> String {
+        let mut enc = Encoder::new(tag::STATE_ROOT);
+        enc.put_u64(self.chain_id).put_u64(self.accounts.len() as u64);
+        for (address, account) in &self.accounts {
+            let balances: BTreeMap<&String, &u64> = account.wallet.balances.iter().collect();
+            enc.put_str(address).put_u64(balances.len() as u64);
+            for (asset, amount) in balances {
+                enc.put_str(asset).put_u64(*amount);
+            }
+            enc.put_u64(account.nonce).put_list(&account.vesting);
+        }
+        enc.put_u64(self.burned.len() as u64);
+        for (asset, amount) in &self.burned {
+            enc.put_str(asset).put_u64(*amount);
+        }
+        self.assets.encode(&mut enc);
+        self.staking.encode(&mut enc);
+        enc.hash_hex()
+    }
+
+    /// Корень состояния после применения блока — производитель записывает его в заголовок.
+    pub fn state_root_after(&self, block: &Block) -> Result<String, String> {
+        Ok(self.transition(block)?.root())
+    }
+
+    /// Применяет блок атомарно: если хоть одна транзакция не проходит или
+    /// корень состояния не совпадает с заголовком, состояние остаётся прежним.
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), String> {
+        let next = self.transition(block)?;
+        let root = next.root();
+        if block.header.state_root != root {
+            return Err(format!("Block {}: state root mismatch, header {} but computed {}", block.header.height, block.header.state_root, root));
+        }
+        *self = next;
+        Ok(())
+    }
+
+    fn transition(&self, block: &Block) -> Result<State, String> {
+        let height = block.header.height;
+        let expected = self.height.map_or(0, |h| h + 1);
+        if height != expected {
+            return Err(format!("Block {} applied out of order, expected height {}", height, expected));
+        }
+        // Листья Меркла покрывают только id, поэтому id обязан быть хешем тела
+        if let Some(tx) = block.body.transactions.iter().find(|tx| tx.id != tx.calculate_id()) {
+            return Err(format!("Block {}: transaction id {} does not match its contents", height, tx.id));
+        }
+        let mut next = self.clone();
+        if height == 0 {
+            next.apply_genesis(block)
+                .map_err(|e| format!("Genesis block rejected: {}", e))?;
+        } else {
+            let (coinbase, transactions) = match block.body.transactions.split_first() {
+                Some((coinbase, rest)) if coinbase.is_coinbase() => (coinbase, rest),
+                _ => return Err(format!("Block {}: first transaction must be a coinbase", height)),
+            };
+            // Выводы из стейка, чей период анбондинга истёк, возвращаются до транзакций блока
+            let release_id = format!("unbonded@{}", height);
+            for (index, released) in next.staking.release(height).into_iter().enumerate() {
+                let output = TxOutput { asset: NATIVE_ASSET.into(), recipient: released.delegator, amount: released.amount };
+                next.credit(&release_id, index, &output)?;
+            }
+            let mut fees = 0u64;
+            for tx in transactions {
+                next.apply_transaction(tx, height)
+                    .map_err(|e| format!("Block {}: transaction {} rejected: {}", height, tx.id, e))?;
+                fees = fees.checked_add(tx.fee).ok_or(format!("Block {}: total fees overflow u64", height))?;
+            }
+            next.apply_coinbase(coinbase, block.header.miner_reward, fees)
+                .map_err(|e| format!("Block {}: coinbase rejected: {}", height, e))?;
+        }
+        next.prune_vesting(height);
+        next.height = Some(height);
+        Ok(next)
+    }
+
+    /// Генезис регистрирует активы спецификации, а выходы его транзакций —
+    /// начальные аллокации, они ничего не списывают. Выходы Stake-транзакции
+    /// становятся собственным стейком валидаторов.
+    fn apply_genesis(&mut self, block: &Block) -> Result<(), String> {
+        for tx in &block.body.transactions {
+            tx.validate(&self.utxos)?;
+            if let Some(definition) = &tx.asset_definition {
+                self.assets.register(definition, &tx.sender)?;
+            }
+            for (index, output) in tx.outputs.iter().enumerate() {
+                if output.asset != NATIVE_ASSET {
+                    self.assets.record_mint(&output.asset, output.amount)?;
+                }
+                match tx.tx_type {
+                    TxType::Stake => self.staking.bond(&output.recipient, &output.recipient, output.amount),
+                    _ => self.credit(&tx.id, index, output)?,
+                }
+            }
+            self.add_vesting(tx)?;
+        }
+        Ok(())
+    }
+
+    /// Coinbase создаёт не больше награды из заголовка плюс комиссии блока,
+    /// только в нативном активе; точное распределение проверяет `RewardPolicy`.
+    fn apply_coinbase(&mut self, coinbase: &Transaction, reward: u64, fees: u64) -> Result<(), String> {
+        let expected = reward.checked_add(fees).ok_or("Reward plus fees overflow u64")?;
+        if coinbase.chain_id != self.chain_id {
+            return Err(format!("Coinbase is for chain {}, expected {}", coinbase.chain_id, self.chain_id));
+        }
+        if !coinbase.inputs.is_empty() || coinbase.fee != 0 {
+            return Err("Coinbase must not have inputs or a fee".into());
+        }
+        if coinbase.outputs.iter().any(|o| o.asset != NATIVE_ASSET) {
+            return Err(format!("Coinbase may only pay {}", NATIVE_ASSET));
+        }
+        let paid = coinbase.total_amount()?;
+        if paid > expected {
+            return Err(format!("Coinbase pays {}, more than reward plus fees {}", paid, expected));
+        }
+        for (index, output) in coinbase.outputs.iter().enumerate() {
+            self.credit(&coinbase.id, index, output)?;
+        }
+        // Меньшая сумма допустима: невыплаченные комиссии сжигаются, а
+        // невыплаченная субсидия просто не выпускается
+        self.record_burn(NATIVE_ASSET, (expected - paid).min(fees));
+        Ok(())
+    }
+
+    /// Зачисляет выход: на баланс получателя и в UTXO-набор.
+    fn credit(&mut self, tx_id: &str, index: usize, output: &TxOutput) -> Result<(), String> {
+        self.utxos.create(tx_id, index as u32, output.clone())?;
+        self.account_mut(&output.recipient).wallet.add_tokens(&output.asset, output.amount);
+        Ok(())
+    }
+
+    /// Списывает `debits` с баланса отправителя и тратит столько же в UTXO:
+    /// указанные входы, а без них — выходы отправителя. Излишек потраченного
+    /// возвращается отправителю выходами-сдачей после выходов транзакции.
+    fn debit(&mut self, tx: &Transaction, debits: &BTreeMap<&str, u64>, height: u64) -> Result<(), String> {
+        let sender = self.account_mut(&tx.sender);
+        for (asset, amount) in debits {
+            sender.debit(asset, *amount, height)?;
+        }
+        let spent = if tx.inputs.is_empty() {
+            let mut spent = BTreeMap::new();
+            for (asset, amount) in debits {
+                spent.insert(asset.to_string(), self.utxos.spend_from(&tx.sender, asset, *amount)?);
+            }
+            spent
+        } else {
+            self.utxos.spend_inputs(tx)?
+        };
+        let mut index = tx.outputs.len() as u32;
+        for (asset, amount) in spent {
+            let debited = debits.get(asset.as_str()).copied().unwrap_or(0);
+            let change = amount.checked_sub(debited)
+                .ok_or_else(|| format!("Inputs cover {} {}, but {} required", amount, asset, debited))?;
+            if change > 0 {
+                self.utxos.create(&tx.id, index, TxOutput { asset, recipient: tx.sender.clone(), amount: change })?;
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn record_burn(&mut self, asset: &str, amount: u64) {
+        if amount > 0 {
+            *self.burned.entry(asset.to_string()).or_insert(0) += amount;
+        }
+    }
+
+    /// Выходы перевода с графиком вестинга блокируются у получателей.
+    fn add_vesting(&mut self, tx: &Transaction) -> Result<(), String> {
+        if let Some(schedule) = &tx.vesting {
+            schedule.validate()?;
+            for output in &tx.outputs {
+                let lock = VestingLock { asset: output.asset.clone(), amount: output.amount, schedule: schedule.clone() };
+                self.account_mut(&output.recipient).vesting.push(lock);
+            }
+        }
+        Ok(())
+    }
+
+    /// Полностью разблокированные записи вестинга больше ничего не ограничивают
+    /// и удаляются, чтобы не копиться в состоянии и его корне.
+    fn prune_vesting(&mut self, height: u64) {
+        for account in self.accounts.values_mut() {
+            account.vesting.retain(|lock| lock.locked(height) > 0);
+        }
+    }
+
+    fn apply_transaction(&mut self, tx: &Transaction, height: u64) -> Result<(), String> {
+        if !tx.verify_signer() {
+            return Err(format!("Transaction is not signed by the key of {}", tx.sender));
+        }
+        self.check_replay(tx, self.next_nonce(&tx.sender))?;
+        tx.validate(&self.utxos)?;
+        self.assets.check(tx)?;
+        let staking = matches!(tx.tx_type, TxType::Stake | TxType::Unstake);
+        if staking && tx.outputs.iter().any(|o| o.asset != NATIVE_ASSET) {
+            return Err(format!("Only {} can be staked", NATIVE_ASSET));
+        }
+        if let Some(o) = tx.outputs.iter().find(|o| matches!(tx.tx_type, TxType::Stake) && !is_valid_address(&o.recipient)) {
+            return Err(format!("Cannot stake to {}: not a validator address", o.recipient));
+        }
+        let debits: BTreeMap<&str, u64> = tx.debits()?.into_iter().collect();
+        self.debit(tx, &debits, height)?;
+        self.account_mut(&tx.sender).nonce += 1;
+
+        match tx.tx_type {
+            // Получатель выхода — валидатор, к которому привязывается стейк
+            TxType::Stake => {
+                for output in &tx.outputs {
+                    self.staking.bond(&output.recipient, &tx.sender, output.amount);
+                }
+            }
+            TxType::Unstake => {
+                for output in &tx.outputs {
+                    self.staking.unbond(&output.recipient, &tx.sender, output.amount, height, self.staking_params.unbonding_period)?;
+                }
+            }
+            TxType::Transfer | TxType::ContractCall => {
+                for (index, output) in tx.outputs.iter().enumerate() {
+                    self.credit(&tx.id, index, output)?;
+                }
+                self.add_vesting(tx)?;
+            }
+            TxType::IssueAsset | TxType::Mint => {
+                if let Some(definition) = &tx.asset_definition {
+                    self.assets.register(definition, &tx.sender)?;
+                }
+                for (index, output) in tx.outputs.iter().enumerate() {
+                    self.assets.record_mint(&output.asset, output.amount)?;
+                    self.credit(&tx.id, index, output)?;
+                }
+            }
+            TxType::Burn => {
+                for output in &tx.outputs {
+                    self.record_burn(&output.asset, output.amount);
+                }
+            }
+            TxType::Coinbase => return Err("Coinbase is only allowed as the first transaction of a block".into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset_registry::AssetDefinition;
+    use crate::transaction::TxInput;
+    use crate::vesting::VestingSchedule;
+    use crate::wallet::{generate_address, keypair_from_seed};
+
+    fn output(asset: &str, recipient: &str, amount: u64) -> TxOutput {
+        TxOutput { asset: asset.into(), recipient: recipient.into(), amount }
+    }
+
+    /// Адрес тестового аккаунта, ключ которого выводится из его имени.
+    fn addr(name: &str) -> String {
+        generate_address(&keypair_from_seed(name).public)
+    }
+
+    fn signed(name: &str, mut tx: Transaction) -> Transaction {
+        tx.sign(&keypair_from_seed(name));
+        tx
+    }
+
+    fn build_block(state: &State, height: u64, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(height, format!("h{}", height), height, transactions, "Hybrid".into());
+        block.header.producer = "miner".into();
+        if height > 0 {
+            block.insert_coinbase(state.chain_id).unwrap();
+        }
+        if let Ok(root) = state.state_root_after(&block) {
+            block.header.state_root = root;
+        }
+        block
+    }
+
+    fn genesis() -> Block {
+        let allocation = Transaction::new("genesis".into(), vec![output("TRD", &addr("alice"), 100)], 0);
+        build_block(&State::default(), 0, vec![allocation])
+    }
+
+    #[test]
+    fn test_apply_transfer_fee_and_reward() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let tx = signed("alice", Transaction::new(addr("alice"), vec![output("TRD", &addr("bob"), 30)], 2));
+        let block = build_block(&state, 1, vec![tx]);
+        state.apply_block(&block).unwrap();
+        assert_eq!(state.balance(&addr("alice"), "TRD"), 68);
+        assert_eq!(state.balance(&addr("bob"), "TRD"), 30);
+        assert_eq!(state.balance("miner", "TRD"), block.header.miner_reward + 2);
+        assert_eq!(state.account(&addr("alice")).unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn test_utxo_set_matches_account_balances() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let allocation = genesis().body.transactions[0].id.clone();
+        let transfer = signed("alice", Transaction::new(addr("alice"), vec![output("TRD", &addr("bob"), 30)], 2));
+        let received = TxInput { tx_id: transfer.id.clone(), output_index: 0 };
+        state.apply_block(&build_block(&state, 1, vec![transfer])).unwrap();
+
+        let spend = Transaction::new_with_inputs(addr("bob"), vec![received], vec![output("TRD", "carol", 20)], 1);
+        let mut stale = Transaction::new_with_inputs(addr("alice"), vec![TxInput { tx_id: allocation, output_index: 0 }], vec![output("TRD", "carol", 50)], 0);
+        stale.nonce = 1;
+        assert!(state.state_root_after(&build_block(&state, 2, vec![signed("alice", stale)])).is_err(), "An account transfer spends the allocation output");
+        state.apply_block(&build_block(&state, 2, vec![signed("bob", spend)])).unwrap();
+
+        assert_eq!((state.balance(&addr("bob"), "TRD"), state.balance("carol", "TRD")), (9, 20));
+        for (address, account) in &state.accounts {
+            for (asset, balance) in &account.wallet.balances {
+                assert_eq!(state.utxos.balance(address, asset), *balance, "{} {} diverged", address, asset);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transaction_id_must_match_contents() {
+        let genesis = genesis();
+        let state = State::from_blocks(State::default(), std::slice::from_ref(&genesis)).unwrap();
+        let mut block = build_block(&state, 1, Vec::new());
+        block.body.transactions[0].id = genesis.body.transactions[0].id.clone();
+        block.header.merkle_root = block.calculate_merkle_root();
+        assert!(state.state_root_after(&block).is_err(), "A coinbase must not reuse another transaction's id");
+
+        let mut forged = genesis.clone();
+        forged.body.transactions[0].outputs[0].amount = 1_000;
+        assert!(State::default().state_root_after(&forged).is_err(), "Genesis transactions are checked too");
+    }
+
+    #[test]
+    fn test_overspending_block_is_rejected() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let tx = signed("alice", Transaction::new(addr("alice"), vec![output("TRD", "bob", 100)], 1));
+        let block = build_block(&state, 1, vec![tx]);
+        assert!(state.apply_block(&block).is_err());
+        assert_eq!(state.balance(&addr("alice"), "TRD"), 100, "Rejected block must not change state");
+        assert_eq!(state.height, Some(0));
+    }
+
+    #[test]
+    fn test_unsigned_or_foreign_signed_transfer_is_rejected() {
+        let state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let unsigned = Transaction::new(addr("alice"), vec![output("TRD", "bob", 10)], 1);
+        assert!(state.state_root_after(&build_block(&state, 1, vec![unsigned.clone()])).is_err(), "Unsigned transfers must be rejected");
+        let forged = signed("mallory", unsigned);
+        assert!(state.state_root_after(&build_block(&state, 1, vec![forged])).is_err(), "Only the sender's key may sign");
+    }
+
+    #[test]
+    fn test_replayed_transaction_is_rejected() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let tx = signed("alice", Transaction::new(addr("alice"), vec![output("TRD", "bob", 10)], 1));
+        let first = build_block(&state, 1, vec![tx.clone()]);
+        state.apply_block(&first).unwrap();
+        assert_eq!(state.next_nonce(&addr("alice")), 1);
+
+        let replay = build_block(&state, 2, vec![tx]);
+        assert!(state.apply_block(&replay).is_err(), "Same nonce must not be accepted twice");
+
+        let mut foreign = Transaction::new(addr("alice"), vec![output("TRD", "bob", 10)], 1);
+        foreign.nonce = 1;
+        foreign.chain_id = state.chain_id + 1;
+        let foreign = build_block(&state, 2, vec![signed("alice", foreign)]);
+        assert!(state.apply_block(&foreign).is_err(), "Transactions from another chain must be rejected");
+    }
+
+    #[test]
+    fn test_coinbase_must_match_reward_and_fees() {
+        let state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let tx = signed("alice", Transaction::new(addr("alice"), vec![output("TRD", "bob", 10)], 3));
+        let mut block = build_block(&state, 1, vec![tx]);
+        block.body.transactions[0].outputs[0].amount += 1;
+        block.body.transactions[0].id = block.body.transactions[0].calculate_id();
+        assert!(state.state_root_after(&block).is_err(), "Coinbase must not pay more than reward plus fees");
+
+        let mut underpaid = build_block(&state, 1, Vec::new());
+        underpaid.body.transactions[0].outputs[0].amount -= 1;
+        underpaid.body.transactions[0].id = underpaid.body.transactions[0].calculate_id();
+        let after = state.transition(&underpaid).unwrap();
+        assert!(after.burned.is_empty(), "Unpaid subsidy is never issued, so it is not burned");
+
+        let mut missing = build_block(&state, 1, Vec::new());
+        missing.body.transactions.clear();
+        assert!(state.state_root_after(&missing).is_err(), "Blocks without a coinbase are invalid");
+
+        let mut second = build_block(&state, 1, Vec::new());
+        let extra = second.body.transactions[0].clone();
+        second.body.transactions.push(extra);
+        assert!(state.state_root_after(&second).is_err(), "Only one coinbase per block");
+    }
+
+    #[test]
+    fn test_state_root_must_match_header() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let tx = signed("alice", Transaction::new(addr("alice"), vec![output("TRD", "bob", 10)], 1));
+        let mut forged = build_block(&state, 1, vec![tx]);
+        forged.header.state_root = State::default().root();
+        assert!(state.apply_block(&forged).is_err());
+    }
+
+    #[test]
+    fn test_issue_mint_and_burn_assets() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let definition = AssetDefinition {
+            symbol: "GOLD".into(),
+            decimals: 2,
+            max_supply: 100,
+            mint_authority: Some(addr("alice")),
+            burn_authority: Some(addr("bob")),
+        };
+        let issue = Transaction::new_issue_asset(addr("alice"), definition, vec![output("GOLD", &addr("bob"), 40)], 1);
+        let mut mint = Transaction::new(addr("alice"), vec![output("GOLD", &addr("alice"), 60)], 1);
+        mint.tx_type = TxType::Mint;
+        mint.nonce = 1;
+        state.apply_block(&build_block(&state, 1, vec![signed("alice", issue), signed("alice", mint)])).unwrap();
+        assert_eq!((state.balance(&addr("bob"), "GOLD"), state.balance(&addr("alice"), "GOLD")), (40, 60));
+
+        let mut overmint = Transaction::new(addr("alice"), vec![output("GOLD", &addr("alice"), 1)], 0);
+        overmint.tx_type = TxType::Mint;
+        overmint.nonce = 2;
+        assert!(state.state_root_after(&build_block(&state, 2, vec![signed("alice", overmint)])).is_err(), "Max supply is reached");
+
+        let mut burn = Transaction::new(addr("bob"), vec![output("GOLD", &addr("bob"), 15)], 0);
+        burn.tx_type = TxType::Burn;
+        state.apply_block(&build_block(&state, 2, vec![signed("bob", burn)])).unwrap();
+        assert_eq!(state.balance(&addr("bob"), "GOLD"), 25);
+        assert_eq!(state.burned.get("GOLD"), Some(&15));
+        assert_eq!(state.assets.get("GOLD").unwrap().minted, 100, "Burning does not free max supply");
+    }
+
+    #[test]
+    fn test_vesting_tokens_unlock_by_height() {
+        let mut state = State::from_blocks(State::default(), &[genesis()]).unwrap();
+        let mut grant = Transaction::new(addr("alice"), vec![output("TRD", &addr("bob"), 40)], 0);
+        grant.vesting = Some(VestingSchedule { start_height: 1, cliff_height: 3, end_height: 5 });
+        state.apply_block(&build_block(&state, 1, vec![signed("alice", grant)])).unwrap();
+        assert_eq!((state.balance(&addr("bob"), "TRD"), state.locked_balance(&addr("bob"), "TRD"), state.spendable_balance(&addr("bob"), "TRD")), (40, 40, 0));
+
+        let spend = |amount, nonce| {
+            let mut tx = Transaction::new(addr("bob"), vec![output("TRD", "carol", amount)], 0);
+            tx.nonce = nonce;
+            signed("bob", tx)
+        };
+        assert!(state.state_root_after(&build_block(&state, 2, vec![spend(1, 0)])).is_err(), "Nothing is spendable before the cliff");
+        state.apply_block(&build_block(&state, 2, Vec::new())).unwrap();
+        // На высоте 3 разблокирована половина: 40 * (3 - 1) / (5 - 1)
+        assert!(state.state_root_after(&build_block(&state, 3, vec![spend(21, 0)])).is_err());
+        state.apply_block(&build_block(&state, 3, vec![spend(20, 0)])).unwrap();
+        assert_eq!((state.balance(&addr("bob"), "TRD"), state.locked_balance(&addr("bob"), "TRD")), (20, 20));
+        state.apply_block(&build_block(&state, 4, Vec::new())).unwrap();
+        state.apply_block(&build_block(&state, 5, Vec::new())).unwrap();
+        assert!(state.account(&addr("bob")).unwrap().vesting.is_empty(), "Fully vested locks are pruned");
+
+        let mut invalid = Transaction::new(addr("alice"), vec![output("TRD", &addr("bob"), 1)], 0);
+        invalid.nonce = 1;
+        invalid.vesting = Some(VestingSchedule { start_height: 10, cliff_height: 5, end_height: 20 });
+        assert!(state.state_root_after(&build_block(&state, 6, vec![signed("alice", invalid)])).is_err());
+    }
+
+    #[test]
+    fn test_stake_unbonds_after_period() {
+        const UNBONDING_PERIOD: u64 = 5;
+        let mut state = State::default().with_staking_params(StakingParams { unbonding_period: UNBONDING_PERIOD });
+        state.apply_block(&genesis()).unwrap();
+        let mut invalid = Transaction::new(addr("alice"), vec![output("TRD", "validator", 60)], 0);
+        invalid.tx_type = TxType::Stake;
+        assert!(state.state_root_after(&build_block(&state, 1, vec![signed("alice", invalid)])).is_err(), "Stake must go to a validator address");
+
+        let validator = addr("validator");
+        let mut stake = Transaction::new(addr("alice"), vec![output("TRD", &validator, 60)], 0);
+        stake.tx_type = TxType::Stake;
+        let mut unstake = Transaction::new(addr("alice"), vec![output("TRD", &validator, 25)], 0);
+        unstake.tx_type = TxType::Unstake;
+        unstake.nonce = 1;
+        state.apply_block(&build_block(&state, 1, vec![signed("alice", stake), signed("alice", unstake)])).unwrap();
+        assert_eq!((state.balance(&addr("alice"), "TRD"), state.staking.validator_stake(&validator)), (40, 35));
+        assert_eq!(state.staking.bonded_by(&addr("alice")), 35);
+
+        let mut overdraw = Transaction::new(addr("alice"), vec![output("TRD", &validator, 36)], 0);
+        overdraw.tx_type = TxType::Unstake;
+        overdraw.nonce = 2;
+        assert!(state.state_root_after(&build_block(&state, 2, vec![signed("alice", overdraw)])).is_err(), "Cannot unbond more than bonded");
+
+        for height in 2..=UNBONDING_PERIOD {
+            state.apply_block(&build_block(&state, height, Vec::new())).unwrap();
+        }
+        assert_eq!(state.balance(&addr("alice"), "TRD"), 40, "Unbonding funds are still locked");
+        state.apply_block(&build_block(&state, UNBONDING_PERIOD + 1, Vec::new())).unwrap();
+        assert_eq!(state.balance(&addr("alice"), "TRD"), 65);
+        assert!(state.staking.unbonding().is_empty());
+    }
+}
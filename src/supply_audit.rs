@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use crate::block::Block;
+use crate::chain_spec::ChainSpec;
+use crate::state::State;
+use crate::token_economy::{TokenEconomy, NATIVE_ASSET};
+
+/// Предложение одного актива на высоте аудита.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AssetSupply {
+    pub asset: String,
+    /// Свободные балансы аккаунтов.
+    pub circulating: u64,
+    pub staked: u64,
+    /// Заблокированные средства: ещё не разблокированный вестинг и выводы из
+    /// стейка в периоде анбондинга. HTLC из `atomic_swap` живут вне состояния
+    /// цепочки и сюда не входят.
+    pub locked: u64,
+    pub burned: u64,
+    /// Выпуск: для нативного актива — генезис плюс эмиссия по спецификации до
+    /// высоты аудита, для остальных — выпуск по реестру активов.
+    pub issued: u64,
+}
+
+impl AssetSupply {
+    /// Всё, что ещё существует: свободное, застейканное и заблокированное.
+    pub fn outstanding(&self) -> u64 {
+        self.circulating + self.staked + self.locked
+    }
+}
+
+/// Результат сверки предложения с состоянием цепочки.
+#[derive(Clone, Debug, Serialize)]
+pub struct SupplyReport {
+    pub height: u64,
+    pub assets: Vec<AssetSupply>,
+    /// Предложение нативного актива по учёту `TokenEconomy` на этой высоте.
+    pub tracked_supply: Option<u64>,
+    pub discrepancies: Vec<String>,
+}
+
+impl SupplyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+fn entry<'a>(supply: &'a mut BTreeMap<String, AssetSupply>, asset: &str) -> &'a mut AssetSupply {
+    supply.entry(asset.to_string()).or_insert_with(|| AssetSupply { asset: asset.to_string(), ..AssetSupply::default() })
+}
+
+/// Предложение по активам так, как его видит состояние.
+pub fn ledger_supply(state: &State) -> BTreeMap<String, AssetSupply> {
+    let mut supply = BTreeMap::new();
+    let height = state.height.unwrap_or(0);
+    for account in state.accounts.values() {
+        for (asset, amount) in &account.wallet.balances {
+            let locked = account.locked(asset, height);
+            let s = entry(&mut supply, asset);
+            s.circulating += amount.saturating_sub(locked);
+            s.locked += locked;
+        }
+    }
+    let native = entry(&mut supply, NATIVE_ASSET);
+    native.staked += state.staking.total_bonded();
+    native.locked += state.staking.unbonding().iter().map(|u| u.amount).sum::<u64>();
+    for (asset, amount) in &state.burned {
+        entry(&mut supply, asset).burned += amount;
+    }
+    supply
+}
+
+/// Пересчитывает предложение на высоте `height` по блокам основной цепочки и
+/// сверяет его с выпуском по спецификации и с учётом `economy`. Расхождения
+/// попадают в отчёт, а не в ошибку; ошибка — только если цепочку нельзя
+/// воспроизвести до этой высоты.
+pub fn audit(spec: &ChainSpec, blocks: &[Block], height: u64, economy: &TokenEconomy) -> Result<SupplyReport, String> {
+    let replayed = blocks.get(..=height as usize)
+        .ok_or(format!("Height {} is above the chain tip", height))?;
+    if let Some(pair) = replayed.windows(2).find(|pair| pair[1].header.parent_hash != pair[0].calculate_hash()) {
+        return Err(format!("Block {} does not extend block {}", pair[1].header.height, pair[0].header.height));
+    }
+    let mut state = spec.initial_state();
+    for block in replayed {
+        state.apply_block(block)?;
+    }
+    audit_state(spec, &state, economy)
+}
+
+/// Сверка уже посчитанного состояния на его высоте, без воспроизведения
+/// блоков — например, состояния на вершине хранилища.
+pub fn audit_state(spec: &ChainSpec, state: &State, economy: &TokenEconomy) -> Result<SupplyReport, String> {
+    let height = state.height.ok_or("State has no blocks to audit")?;
+    let mut supply = ledger_supply(state);
+    let genesis_native = spec.genesis_supply()?.get(NATIVE_ASSET).copied().unwrap_or(0);
+    entry(&mut supply, NATIVE_ASSET).issued = genesis_native + spec.monetary.emission.total_emission(height);
+    for (asset, info) in state.assets.iter() {
+        entry(&mut supply, asset).issued = info.minted;
+    }
+
+    let mut discrepancies = Vec::new();
+    for s in supply.values() {
+        if s.outstanding() + s.burned != s.issued {
+            discrepancies.push(format!("{}: outstanding {} plus burned {} does not match issued {}", s.asset, s.outstanding(), s.burned, s.issued));
+        }
+    }
+    let tracked_supply = economy.supply_at(height);
+    let native = supply.get(NATIVE_ASSET).map_or(0, AssetSupply::outstanding);
+    match tracked_supply {
+        Some(tracked) if tracked != native => discrepancies.push(format!("Tracked supply {} differs from ledger supply {} of {}", tracked, native, NATIVE_ASSET)),
+        Some(_) => {}
+        None => discrepancies.push(format!("Token economy has no record at height {}", height)),
+    }
+
+    Ok(SupplyReport { height, assets: supply.into_values().collect(), tracked_supply, discrepancies })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_spec::shipped_spec;
+    use crate::chain_store::{ChainStore, MemoryChainStore};
+    use crate::transaction::{Transaction, TxOutput};
+    use crate::wallet::{generate_address, keypair_from_seed};
+
+    /// Генезис и блок с одним переводом и комиссией, делящейся по спецификации.
+    fn build_chain(spec: &ChainSpec) -> MemoryChainStore {
+        let mut chain = MemoryChainStore::with_initial_state(spec.initial_state());
+        let genesis = spec.genesis_block();
+        chain.put_block(genesis.clone()).unwrap();
+
+        let user = keypair_from_seed("triad-dev-user");
+        let sender = spec.allocations[0].address.clone();
+        let mut tx = Transaction::new(sender, vec![TxOutput { asset: NATIVE_ASSET.into(), recipient: "bob".into(), amount: 10 }], 10);
+        tx.chain_id = spec.chain_id;
+        tx.sign(&user);
+        let mut block = Block::new(1, genesis.calculate_hash(), genesis.header.timestamp + 60, vec![tx], spec.consensus.name().into());
+        let miner = keypair_from_seed("triad-dev-miner");
+        block.header.producer = generate_address(&miner.public);
+        block.header.miner_reward = spec.monetary.emission.subsidy(1);
+        block.insert_coinbase_with(spec.chain_id, &spec.monetary.fees).unwrap();
+        block.header.state_root = chain.state().state_root_after(&block).unwrap();
+        block.sign(&miner);
+        block.hash = Some(block.calculate_hash());
+        chain.put_block(block).unwrap();
+        chain
+    }
+
+    #[test]
+    fn test_audit_reconciles_ledger_and_economy() {
+        let spec = shipped_spec();
+        let chain = build_chain(&spec);
+        let blocks = chain.blocks().unwrap();
+        let mut economy = spec.economy();
+        economy.sync(&chain).unwrap();
+
+        let report = audit(&spec, &blocks, 1, &economy).unwrap();
+        assert!(report.is_consistent(), "{:?}", report.discrepancies);
+        let native = report.assets.iter().find(|s| s.asset == NATIVE_ASSET).unwrap();
+        assert_eq!((native.burned, native.staked), (2, 100));
+        assert_eq!(report.tracked_supply, Some(native.outstanding()));
+        assert!(audit(&spec, &blocks, 0, &economy).unwrap().is_consistent());
+        assert!(audit(&spec, &blocks, 2, &economy).is_err());
+        assert_eq!(audit_state(&spec, chain.state(), &economy).unwrap().assets, report.assets, "Tip state must audit like a full replay");
+
+        let mut detached = blocks.clone();
+        detached[1].header.parent_hash = "0".repeat(64);
+        assert!(audit(&spec, &detached, 1, &economy).is_err(), "Blocks from different branches must not be replayed together");
+
+        economy.burn_tokens(1);
+        economy.settle_block(&blocks[1]);
+        let report = audit(&spec, &blocks, 1, &economy).unwrap();
+        assert_eq!(report.discrepancies.len(), 1, "A drifted tracked supply must be reported");
+    }
+}
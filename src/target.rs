@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Беззнаковое 256-битное число (четыре 64-битных слова, младшее первым)
+/// для целей PoW и накопленной работы цепочки.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+
+    /// Интерпретирует байты хеша как big-endian число.
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        let mut words = [0u64; 4];
+        for (i, word) in words.iter_mut().enumerate() {
+            let start = 32 - 8 * (i + 1);
+            *word = u64::from_be_bytes(bytes[start..start + 8].try_into().unwrap());
+        }
+        U256(words)
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, word) in self.0.iter().enumerate() {
+            let start = 32 - 8 * (i + 1);
+            bytes[start..start + 8].copy_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|w| *w == 0)
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// Количество значащих бит.
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+
+    fn bit(&self, index: u32) -> bool {
+        self.0[(index / 64) as usize] >> (index % 64) & 1 == 1
+    }
+
+    pub fn shl_bits(self, shift: u32) -> Self {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        let mut out = [0u64; 4];
+        for i in (words..4).rev() {
+            out[i] = self.0[i - words] << bits;
+            if bits > 0 && i > words {
+                out[i] |= self.0[i - words - 1] >> (64 - bits);
+            }
+        }
+        U256(out)
+    }
+
+    pub fn shr_bits(self, shift: u32) -> Self {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        let mut out = [0u64; 4];
+        for (i, word) in out.iter_mut().enumerate().take(4 - words) {
+            *word = self.0[i + words] >> bits;
+            if bits > 0 && i + words + 1 < 4 {
+                *word |= self.0[i + words + 1] << (64 - bits);
+            }
+        }
+        U256(out)
+    }
+
+    pub fn checked_add(self, other: U256) -> Option<U256> {
+        let mut out = [0u64; 4];
+        let mut carry = false;
+        for (i, word) in out.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *word = sum;
+            carry = c1 || c2;
+        }
+        if carry { None } else { Some(U256(out)) }
+    }
+
+    pub fn saturating_add(self, other: U256) -> U256 {
+        self.checked_add(other).unwrap_or(U256::MAX)
+    }
+
+    fn wrapping_sub(self, other: U256) -> U256 {
+        let mut out = [0u64; 4];
+        let mut borrow = false;
+        for (i, word) in out.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *word = diff;
+            borrow = b1 || b2;
+        }
+        U256(out)
+    }
+
+    pub fn checked_mul_u64(self, factor: u64) -> Option<U256> {
+        let mut out = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, word) in out.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * factor as u128 + carry;
+            *word = product as u64;
+            carry = product >> 64;
+        }
+        if carry != 0 { None } else { Some(U256(out)) }
+    }
+
+    pub fn div_u64(self, divisor: u64) -> U256 {
+        let mut out = [0u64; 4];
+        let mut remainder = 0u128;
+        for i in (0..4).rev() {
+            let current = (remainder << 64) | self.0[i] as u128;
+            out[i] = (current / divisor as u128) as u64;
+            remainder = current % divisor as u128;
+        }
+        U256(out)
+    }
+
+    /// Деление столбиком; делитель должен быть ненулевым.
+    pub fn div_u256(self, divisor: U256) -> U256 {
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            remainder = remainder.shl_bits(1);
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        quotient
+    }
+
+    /// Цель в компактном формате nBits: старший байт — длина числа в байтах,
+    /// младшие три — мантисса. Отрицательные и переполняющие значения некорректны.
+    pub fn from_compact(bits: u32) -> Option<U256> {
+        let size = bits >> 24;
+        let mantissa = bits & 0x007f_ffff;
+        if bits & 0x0080_0000 != 0 && mantissa != 0 {
+            return None;
+        }
+        if size <= 3 {
+            return Some(U256::from_u64((mantissa >> (8 * (3 - size))) as u64));
+        }
+        let value = U256::from_u64(mantissa as u64);
+        if mantissa != 0 && value.bits() + 8 * (size - 3) > 256 {
+            return None;
+        }
+        Some(value.shl_bits(8 * (size - 3)))
+    }
+
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut mantissa = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            self.shr_bits(8 * (size - 3)).low_u64() as u32
+        };
+        // Старший бит мантиссы — знаковый, его нельзя занимать.
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        mantissa | (size << 24)
+    }
+
+    pub fn to_hex(&self) -> String {
+        crate::encoding::to_hex(&self.to_be_bytes())
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self.to_hex();
+        let digits = hex.trim_start_matches('0');
+        write!(f, "0x{}", if digits.is_empty() { "0" } else { digits })
+    }
+}
+
+/// Хеш удовлетворяет цели, если как число он не больше неё.
+pub fn meets_target(hash: &[u8; 32], target: &U256) -> bool {
+    U256::from_be_bytes(hash) <= *target
+}
+
+/// Ожидаемое число попыток для цели: 2^256 / (target + 1).
+/// Считается как !target / (target + 1) + 1, чтобы не выходить за 256 бит.
+pub fn work_for_target(target: &U256) -> U256 {
+    match target.checked_add(U256::ONE) {
+        Some(denominator) => U256::MAX.wrapping_sub(*target).div_u256(denominator).saturating_add(U256::ONE),
+        None => U256::ONE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_roundtrip() {
+        for bits in [0x1d00ffffu32, 0x1b0404cb, 0x2000ffff, 0x03123456, 0x01120000] {
+            let target = U256::from_compact(bits).unwrap();
+            assert_eq!(target.to_compact(), bits, "bits {:08x}", bits);
+        }
+        let genesis = U256::from_compact(0x1d00ffff).unwrap();
+        assert_eq!(genesis, U256::from_u64(0xffff).shl_bits(208));
+        assert!(U256::from_compact(0x04923456).is_none(), "negative targets are invalid");
+        assert!(U256::from_compact(0xff00ffff).is_none(), "overflowing targets are invalid");
+    }
+
+    #[test]
+    fn test_work_for_target() {
+        let target = U256::ONE.shl_bits(255).wrapping_sub(U256::ONE);
+        assert_eq!(work_for_target(&target), U256::from_u64(2));
+        let bitcoin_genesis = U256::from_compact(0x1d00ffff).unwrap();
+        assert_eq!(work_for_target(&bitcoin_genesis), U256::from_u64(0x0001_0001_0001));
+    }
+
+    #[test]
+    fn test_hash_compared_as_number() {
+        let target = U256::from_compact(0x1f00ffff).unwrap();
+        let mut easy = [0u8; 32];
+        easy[2] = 0xff;
+        let mut hard = [0u8; 32];
+        hard[1] = 0x01;
+        assert!(meets_target(&easy, &target));
+        assert!(!meets_target(&hard, &target));
+    }
+}
@@ -0,0 +1,97 @@
+use chrono::Utc;
+use log::warn;
+use serde::{Serialize, Deserialize};
+use crate::block::{Block, BlockHeader};
+use crate::consensus_plugin::{ChainContext, ConsensusPlugin};
+
+/// Правила времени блока. Время блока должно быть строго больше медианы
+/// времён последних `median_time_span` предков (median-time-past) и не может
+/// опережать часы узла больше чем на `max_future_drift` секунд. Медиана не
+/// убывает от блока к блоку, поэтому на неё могут опираться пересчёт
+/// сложности и таймлоки HTLC.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimestampRules {
+    pub median_time_span: usize,
+    pub max_future_drift: u64,
+}
+
+impl Default for TimestampRules {
+    fn default() -> Self {
+        TimestampRules { median_time_span: 11, max_future_drift: 2 * 60 * 60 }
+    }
+}
+
+/// Медиана времён последних `span` заголовков; `None` для пустой истории.
+pub fn median_time_past(ancestors: &[BlockHeader], span: usize) -> Option<u64> {
+    let mut times: Vec<u64> = ancestors.iter().rev().take(span.max(1)).map(|h| h.timestamp).collect();
+    if times.is_empty() {
+        return None;
+    }
+    times.sort_unstable();
+    Some(times[times.len() / 2])
+}
+
+impl TimestampRules {
+    /// Наименьшее допустимое время следующего блока.
+    pub fn min_timestamp(&self, ancestors: &[BlockHeader]) -> u64 {
+        median_time_past(ancestors, self.median_time_span).map_or(0, |mtp| mtp + 1)
+    }
+
+    pub fn check(&self, header: &BlockHeader, ancestors: &[BlockHeader], now: u64) -> Result<(), String> {
+        if let Some(mtp) = median_time_past(ancestors, self.median_time_span) {
+            if header.timestamp <= mtp {
+                return Err(format!("Timestamp {} is not after median time past {}", header.timestamp, mtp));
+            }
+        }
+        if header.timestamp > now.saturating_add(self.max_future_drift) {
+            return Err(format!("Timestamp {} is more than {}s ahead of local time {}", header.timestamp, self.max_future_drift, now));
+        }
+        Ok(())
+    }
+}
+
+impl ConsensusPlugin for TimestampRules {
+    fn name(&self) -> &'static str {
+        "TimestampRules"
+    }
+
+    fn validate(&self, block: &Block, ctx: &ChainContext) -> bool {
+        match self.check(&block.header, ctx.ancestors, Utc::now().timestamp() as u64) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Block {} has invalid timestamp: {}", block.header.height, e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(times: &[u64]) -> Vec<BlockHeader> {
+        times.iter().map(|&timestamp| BlockHeader { timestamp, ..BlockHeader::default() }).collect()
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        assert_eq!(median_time_past(&[], 11), None);
+        assert_eq!(median_time_past(&headers(&[10, 50, 20]), 11), Some(20));
+        // Учитываются только последние `span` блоков
+        assert_eq!(median_time_past(&headers(&[1000, 10, 50, 20]), 3), Some(20));
+    }
+
+    #[test]
+    fn test_timestamp_bounds() {
+        let rules = TimestampRules { median_time_span: 3, max_future_drift: 100 };
+        let ancestors = headers(&[100, 160, 130]);
+        let header = |timestamp| BlockHeader { timestamp, ..BlockHeader::default() };
+        assert_eq!(rules.min_timestamp(&ancestors), 131);
+        assert!(rules.check(&header(130), &ancestors, 1_000).is_err(), "Must be after median time past");
+        assert!(rules.check(&header(131), &ancestors, 1_000).is_ok());
+        assert!(rules.check(&header(1_101), &ancestors, 1_000).is_err(), "Must not drift too far into the future");
+        assert!(rules.check(&header(1_100), &ancestors, 1_000).is_ok());
+    }
+}
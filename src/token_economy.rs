@@ -1,20 +1,186 @@
-use log::info;
+use std::collections::BTreeMap;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use crate::block::Block;
+use crate::chain_store::ChainStore;
+use crate::consensus_plugin::{ChainContext, ConsensusPlugin};
+use crate::transaction::TxOutput;
 
+/// Нативный актив сети: в нём платятся комиссии и награды.
+pub const NATIVE_ASSET: &str = "TRD";
+/// Знаменатель долей, задаваемых в базисных пунктах.
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// График эмиссии: субсидия блока как функция высоты, только в целых числах,
+/// чтобы производитель и валидаторы считали её одинаково. Каждые
+/// `reduction_interval` блоков субсидия умножается на `retain_bps / 10000`
+/// (5000 — халвинг), но не опускается ниже `tail_emission`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmissionSchedule {
+    pub initial_subsidy: u64,
+    pub reduction_interval: u64,
+    pub retain_bps: u64,
+    pub tail_emission: u64,
+}
+
+impl EmissionSchedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.reduction_interval == 0 {
+            return Err("Reduction interval must be positive".into());
+        }
+        if self.retain_bps > BPS_DENOMINATOR {
+            return Err(format!("Retained share {} bps exceeds {}", self.retain_bps, BPS_DENOMINATOR));
+        }
+        Ok(())
+    }
+
+    /// Субсидия блока на высоте `height`; генезис субсидии не получает.
+    pub fn subsidy(&self, height: u64) -> u64 {
+        if height == 0 {
+            return 0;
+        }
+        let periods = (height - 1) / self.reduction_interval;
+        let mut subsidy = self.initial_subsidy;
+        if self.retain_bps < BPS_DENOMINATOR {
+            for _ in 0..periods {
+                if subsidy <= self.tail_emission {
+                    break;
+                }
+                subsidy = (subsidy as u128 * self.retain_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+            }
+        }
+        subsidy.max(self.tail_emission)
+    }
+
+    /// Суммарная субсидия блоков 1..=`height`. Считается по периодам, а не по
+    /// блокам; после выхода на хвостовую эмиссию — одним умножением.
+    pub fn total_emission(&self, height: u64) -> u64 {
+        let mut total = 0u128;
+        let mut subsidy = self.initial_subsidy;
+        let mut first = 1u64;
+        while first <= height {
+            let current = subsidy.max(self.tail_emission);
+            if current == self.tail_emission || self.retain_bps >= BPS_DENOMINATOR {
+                total += current as u128 * (height - first + 1) as u128;
+                break;
+            }
+            let last = height.min(first.saturating_add(self.reduction_interval - 1));
+            total += current as u128 * (last - first + 1) as u128;
+            if last == height {
+                break;
+            }
+            first = last + 1;
+            subsidy = share(subsidy, self.retain_bps);
+        }
+        total.min(u64::MAX as u128) as u64
+    }
+}
+
+/// Распределение комиссий блока: `burn_bps` сжигается, `treasury_bps` уходит
+/// в казначейство (если оно задано), остаток получает производитель блока.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSplit {
+    pub burn_bps: u64,
+    pub treasury_bps: u64,
+    #[serde(default)]
+    pub treasury: Option<String>,
+}
+
+/// Доли комиссий одного блока.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeDistribution {
+    pub burned: u64,
+    pub producer: u64,
+    pub treasury: u64,
+}
+
+fn share(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64
+}
+
+impl FeeSplit {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.burn_bps.checked_add(self.treasury_bps).is_none_or(|total| total > BPS_DENOMINATOR) {
+            return Err(format!("Burn and treasury shares exceed {} bps", BPS_DENOMINATOR));
+        }
+        if self.treasury_bps > 0 && self.treasury.as_deref().is_none_or(str::is_empty) {
+            return Err("Treasury share requires a treasury address".into());
+        }
+        Ok(())
+    }
+
+    /// Доли округляются вниз, остаток от округления достаётся производителю.
+    pub fn split(&self, fees: u64) -> FeeDistribution {
+        let burned = share(fees, self.burn_bps);
+        let treasury = if self.treasury.is_some() { share(fees, self.treasury_bps) } else { 0 };
+        FeeDistribution { burned, producer: fees - burned - treasury, treasury }
+    }
+
+    /// Выходы coinbase: субсидия и доля комиссий производителю, доля
+    /// казначейства отдельным выходом; сожжённая часть не выплачивается никому.
+    pub fn coinbase_outputs(&self, producer: &str, subsidy: u64, fees: u64) -> Result<Vec<TxOutput>, String> {
+        let distribution = self.split(fees);
+        let amount = subsidy.checked_add(distribution.producer)
+            .ok_or(format!("Subsidy {} plus producer fees {} overflow u64", subsidy, distribution.producer))?;
+        let mut outputs = vec![TxOutput { asset: NATIVE_ASSET.into(), recipient: producer.into(), amount }];
+        if let (Some(treasury), true) = (&self.treasury, distribution.treasury > 0) {
+            outputs.push(TxOutput { asset: NATIVE_ASSET.into(), recipient: treasury.clone(), amount: distribution.treasury });
+        }
+        Ok(outputs)
+    }
+}
+
+/// Учёт общего предложения нативного актива на узле. Помнит предложение
+/// после каждого учтённого блока, чтобы пережить реорганизацию и сверяться
+/// с состоянием на любой высоте (см. `supply_audit`).
+#[derive(Clone)]
 pub struct TokenEconomy {
     pub total_supply: u64,
-    pub inflation_rate: f64,
-    pub burn_rate: f64,
+    pub fees: FeeSplit,
+    initial_supply: u64,
+    /// Высота → (хеш учтённого блока, предложение после него).
+    history: BTreeMap<u64, (String, u64)>,
 }
 
 impl TokenEconomy {
-    pub fn new(initial_supply: u64, inflation_rate: f64, burn_rate: f64) -> Self {
-        TokenEconomy { total_supply: initial_supply, inflation_rate, burn_rate }
+    pub fn new(initial_supply: u64, fees: FeeSplit) -> Self {
+        TokenEconomy { total_supply: initial_supply, fees, initial_supply, history: BTreeMap::new() }
     }
-    
-    pub fn apply_inflation(&mut self) {
-        let additional = (self.total_supply as f64 * self.inflation_rate) as u64;
-        self.total_supply += additional;
-        info!("Inflation applied: +{}, total: {}", additional, self.total_supply);
+
+    /// Учитывает принятый блок: субсидия выпускается, сожжённая часть комиссий
+    /// выводится из обращения.
+    pub fn settle_block(&mut self, block: &Block) {
+        self.mint(block.header.miner_reward);
+        self.burn_tokens(self.fees.split(block.fees()).burned);
+        self.history.insert(block.header.height, (block.calculate_hash(), self.total_supply));
+    }
+
+    /// Предложение после блока на высоте `height`, если он учтён.
+    pub fn supply_at(&self, height: u64) -> Option<u64> {
+        self.history.get(&height).map(|(_, supply)| *supply)
+    }
+
+    /// Приводит учёт к основной цепочке хранилища: блоки, отключённые
+    /// реорганизацией, откатываются, новые блоки учитываются.
+    pub fn sync(&mut self, chain: &dyn ChainStore) -> Result<(), String> {
+        let headers = chain.headers();
+        let kept = self.history.iter().rev()
+            .find(|(height, (hash, _))| headers.get(**height as usize).is_some_and(|header| header.hash() == *hash))
+            .map(|(height, (_, supply))| (*height, *supply));
+        self.history.retain(|height, _| kept.is_some_and(|(last, _)| *height <= last));
+        self.total_supply = kept.map_or(self.initial_supply, |(_, supply)| supply);
+        for height in kept.map_or(0, |(last, _)| last + 1)..headers.len() as u64 {
+            let block = chain.get_by_height(height)?.ok_or(format!("Block {} is missing from the store", height))?;
+            self.settle_block(&block);
+        }
+        Ok(())
+    }
+
+    pub fn mint(&mut self, amount: u64) {
+        self.total_supply = self.total_supply.saturating_add(amount);
+        info!("Minted {} tokens, total: {}", amount, self.total_supply);
     }
     
     pub fn burn_tokens(&mut self, amount: u64) {
@@ -22,3 +188,99 @@ impl TokenEconomy {
         info!("Burned {} tokens, total: {}", amount, self.total_supply);
     }
 }
+
+/// Монетарная политика, которой проверяется награда блока: заголовок должен
+/// заявлять ровно субсидию графика эмиссии на своей высоте, а coinbase —
+/// выплачивать её вместе с комиссиями строго по `fees`.
+#[derive(Clone, Debug)]
+pub struct RewardPolicy {
+    pub schedule: EmissionSchedule,
+    pub fees: FeeSplit,
+}
+
+impl RewardPolicy {
+    pub fn check(&self, block: &Block) -> Result<(), String> {
+        let subsidy = self.schedule.subsidy(block.header.height);
+        if block.header.miner_reward != subsidy {
+            return Err(format!("Block claims reward {}, policy allows {}", block.header.miner_reward, subsidy));
+        }
+        let coinbase = match block.body.transactions.first() {
+            Some(tx) if tx.is_coinbase() => tx,
+            _ => return Err("First transaction must be a coinbase".into()),
+        };
+        if coinbase.outputs != self.fees.coinbase_outputs(&block.header.producer, subsidy, block.fees())? {
+            return Err("Coinbase does not follow the fee split".into());
+        }
+        Ok(())
+    }
+}
+
+impl ConsensusPlugin for RewardPolicy {
+    fn name(&self) -> &'static str {
+        "RewardPolicy"
+    }
+
+    fn validate(&self, block: &Block, _ctx: &ChainContext) -> bool {
+        match self.check(block) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Block {} violates reward policy: {}", block.header.height, e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+
+    fn schedule(retain_bps: u64) -> EmissionSchedule {
+        EmissionSchedule { initial_subsidy: 50, reduction_interval: 10, retain_bps, tail_emission: 3 }
+    }
+
+    #[test]
+    fn test_emission_schedule() {
+        let halving = schedule(5_000);
+        let subsidies: Vec<u64> = [0, 1, 10, 11, 21, 31, 41, 1_000_000].iter().map(|&h| halving.subsidy(h)).collect();
+        assert_eq!(subsidies, vec![0, 50, 50, 25, 12, 6, 3, 3], "Halves every interval down to the tail emission");
+
+        assert_eq!(halving.total_emission(45), (1..=45).map(|h| halving.subsidy(h)).sum::<u64>());
+        let decay = schedule(9_000);
+        assert_eq!((decay.subsidy(11), decay.subsidy(21)), (45, 40));
+        assert_eq!(schedule(BPS_DENOMINATOR).subsidy(u64::MAX), 50);
+        assert!(schedule(BPS_DENOMINATOR + 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_reward_policy() {
+        let policy = RewardPolicy { schedule: schedule(5_000), fees: FeeSplit::default() };
+        let mut block = Block::new(1, "0".into(), 0, Vec::new(), "PoW".into());
+        assert!(policy.check(&block).is_err(), "Coinbase is required");
+        block.insert_coinbase(1).unwrap();
+        assert!(policy.check(&block).is_ok());
+        block.header.miner_reward = 51;
+        assert!(policy.check(&block).is_err(), "Reward above the policy must be rejected");
+    }
+
+    #[test]
+    fn test_fee_split() {
+        let fees = FeeSplit { burn_bps: 2_500, treasury_bps: 1_000, treasury: Some("treasury".into()) };
+        assert_eq!(fees.split(99), FeeDistribution { burned: 24, producer: 66, treasury: 9 });
+
+        let tx = Transaction::new("alice".into(), vec![TxOutput { asset: "TRD".into(), recipient: "bob".into(), amount: 1 }], 99);
+        let mut block = Block::new(1, "0".into(), 0, vec![tx], "PoW".into());
+        block.header.producer = "miner".into();
+        block.insert_coinbase_with(1, &fees).unwrap();
+        let policy = RewardPolicy { schedule: schedule(5_000), fees: fees.clone() };
+        assert!(policy.check(&block).is_ok());
+        assert_eq!(block.body.transactions[0].outputs.iter().map(|o| o.amount).sum::<u64>(), 50 + 66 + 9);
+
+        block.body.transactions[0].outputs.pop();
+        assert!(policy.check(&block).is_err(), "Treasury share must be paid");
+        assert!(FeeSplit { treasury: None, ..fees.clone() }.validate().is_err());
+        assert!(FeeSplit { burn_bps: u64::MAX, ..fees.clone() }.validate().is_err(), "Share overflow must be an error");
+        assert!(fees.coinbase_outputs("miner", u64::MAX, 99).is_err());
+    }
+}
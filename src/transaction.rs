@@ -1,28 +1,89 @@
 use serde::{Serialize, Deserialize};
-use sha2::{Sha256, Digest};
 use chrono::Utc;
 use ed25519_dalek::{Verifier, Keypair, Signature, Signer};
+use std::collections::{HashMap, HashSet};
+use crate::asset_registry::AssetDefinition;
+use crate::encoding::{tag, Encode, Encoder};
+use crate::token_economy::NATIVE_ASSET;
+use crate::utxo::UtxoSet;
+use crate::vesting::VestingSchedule;
+use crate::wallet::generate_address;
+
+/// Идентификатор сети по умолчанию; подпись транзакции привязана к нему.
+pub const DEFAULT_CHAIN_ID: u64 = 1;
+/// Отправитель coinbase-транзакции: награда создаётся блоком, а не списывается.
+pub const COINBASE_SENDER: &str = "coinbase";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TxType {
     Transfer,
     ContractCall,
     Stake,
+    /// Выплата награды блока и собранных комиссий; только первая транзакция блока.
+    Coinbase,
+    /// Регистрирует актив из `asset_definition`; выходы — его начальный выпуск.
+    IssueAsset,
+    /// Выпуск зарегистрированного актива; подписывает полномочие выпуска.
+    Mint,
+    /// Сжигание актива с баланса отправителя — полномочия сжигания.
+    Burn,
+    /// Вывод стейка у валидатора-получателя; средства возвращаются после
+    /// периода анбондинга.
+    Unstake,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TxOutput {
     pub asset: String,
     pub recipient: String,
     pub amount: u64,
 }
 
+impl Encode for TxType {
+    fn encode(&self, enc: &mut Encoder) {
+        let code = match self {
+            TxType::Transfer => 0,
+            TxType::ContractCall => 1,
+            TxType::Stake => 2,
+            TxType::Coinbase => 3,
+            TxType::IssueAsset => 4,
+            TxType::Mint => 5,
+            TxType::Burn => 6,
+            TxType::Unstake => 7,
+        };
+        enc.put_u8(code);
+    }
+}
+
+impl Encode for TxInput {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_str(&self.tx_id).put_u32(self.output_index);
+    }
+}
+
+impl Encode for TxOutput {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_str(&self.asset).put_str(&self.recipient).put_u64(self.amount);
+    }
+}
+
+/// Ссылка на выход предыдущей транзакции: (id транзакции, индекс выхода).
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TxInput {
+    pub tx_id: String,
+    pub output_index: u32,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transaction {
     pub version: u32,
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
     pub tx_type: TxType,
     pub nonce: u64,
     pub sender: String,
+    #[serde(default)]
+    pub inputs: Vec<TxInput>,
     pub outputs: Vec<TxOutput>,
     pub fee: u64,
     pub gas_limit: Option<u64>,
@@ -30,18 +91,32 @@ pub struct Transaction {
     pub payload: Option<String>,
     pub timestamp: u64,
     pub signatures: Vec<Vec<u8>>,
+    /// Открытый ключ подписавшего; его адрес должен совпадать с `sender`.
+    #[serde(default, with = "serde_bytes")]
+    pub public_key: Option<Vec<u8>>,
     pub source_network: Option<String>,
     pub target_network: Option<String>,
+    #[serde(default)]
+    pub asset_definition: Option<AssetDefinition>,
+    /// График, по которому у получателей разблокируются выходы перевода.
+    #[serde(default)]
+    pub vesting: Option<VestingSchedule>,
     pub id: String,
 }
 
+fn default_chain_id() -> u64 {
+    DEFAULT_CHAIN_ID
+}
+
 impl Transaction {
     pub fn new(sender: String, outputs: Vec<TxOutput>, fee: u64) -> Self {
         let mut tx = Transaction {
             version: 1,
+            chain_id: DEFAULT_CHAIN_ID,
             tx_type: TxType::Transfer,
             nonce: 0,
             sender,
+            inputs: Vec::new(),
             outputs,
             fee,
             gas_limit: None,
@@ -49,20 +124,32 @@ impl Transaction {
             payload: None,
             timestamp: Utc::now().timestamp() as u64,
             signatures: Vec::new(),
+            public_key: None,
             source_network: None,
             target_network: None,
+            asset_definition: None,
+            vesting: None,
             id: String::new(),
         };
         tx.id = tx.calculate_id();
         tx
     }
     
+    pub fn new_with_inputs(sender: String, inputs: Vec<TxInput>, outputs: Vec<TxOutput>, fee: u64) -> Self {
+        let mut tx = Transaction::new(sender, outputs, fee);
+        tx.inputs = inputs;
+        tx.id = tx.calculate_id();
+        tx
+    }
+    
     pub fn new_cross_chain(sender: String, outputs: Vec<TxOutput>, fee: u64, source_network: String, target_network: String) -> Self {
         let mut tx = Transaction {
             version: 1,
+            chain_id: DEFAULT_CHAIN_ID,
             tx_type: TxType::Transfer,
             nonce: 0,
             sender,
+            inputs: Vec::new(),
             outputs,
             fee,
             gas_limit: None,
@@ -70,91 +157,199 @@ impl Transaction {
             payload: None,
             timestamp: Utc::now().timestamp() as u64,
             signatures: Vec::new(),
+            public_key: None,
             source_network: Some(source_network),
             target_network: Some(target_network),
+            asset_definition: None,
+            vesting: None,
             id: String::new(),
         };
         tx.id = tx.calculate_id();
         tx
     }
     
+    /// Coinbase-транзакция блока `height`: без входов и подписи, высота
+    /// записывается в nonce, чтобы id не повторялся между блоками.
+    pub fn coinbase(chain_id: u64, height: u64, timestamp: u64, outputs: Vec<TxOutput>) -> Self {
+        let mut tx = Transaction::new(COINBASE_SENDER.into(), outputs, 0);
+        tx.tx_type = TxType::Coinbase;
+        tx.chain_id = chain_id;
+        tx.nonce = height;
+        tx.timestamp = timestamp;
+        tx.id = tx.calculate_id();
+        tx
+    }
+
+    /// Регистрация актива; `outputs` — начальный выпуск, не больше `max_supply`.
+    pub fn new_issue_asset(sender: String, definition: AssetDefinition, outputs: Vec<TxOutput>, fee: u64) -> Self {
+        let mut tx = Transaction::new(sender, outputs, fee);
+        tx.tx_type = TxType::IssueAsset;
+        tx.asset_definition = Some(definition);
+        tx.id = tx.calculate_id();
+        tx
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.tx_type, TxType::Coinbase)
+    }
+
+    /// Что списывается с баланса отправителя по активам: выходы (кроме
+    /// выпуска, который их создаёт, и Unstake, возвращающего их из стейка)
+    /// плюс комиссия в нативном активе.
+    pub fn debits(&self) -> Result<HashMap<&str, u64>, String> {
+        let mut debits = HashMap::new();
+        if !matches!(self.tx_type, TxType::IssueAsset | TxType::Mint | TxType::Unstake) {
+            for output in &self.outputs {
+                add_amount(&mut debits, &output.asset, output.amount)?;
+            }
+        }
+        add_amount(&mut debits, NATIVE_ASSET, self.fee)?;
+        Ok(debits)
+    }
+
+    /// Сумма всех выходов плюс комиссия; переполнение — ошибка, а не паника.
+    pub fn total_amount(&self) -> Result<u64, String> {
+        self.outputs.iter().try_fold(self.fee, |total, o| total.checked_add(o.amount))
+            .ok_or_else(|| format!("Transaction {} amounts overflow u64", self.id))
+    }
+
+    /// Все семантически значимые поля транзакции; не включает id и подписи.
+    fn encode_body(&self, enc: &mut Encoder) {
+        enc.put_u32(self.version)
+            .put_u64(self.chain_id)
+            .put(&self.tx_type)
+            .put_u64(self.nonce)
+            .put_str(&self.sender)
+            .put_list(&self.inputs)
+            .put_list(&self.outputs)
+            .put_u64(self.fee)
+            .put_option(&self.gas_limit)
+            .put_option(&self.gas_price)
+            .put_option(&self.payload)
+            .put_u64(self.timestamp)
+            .put_option(&self.source_network)
+            .put_option(&self.target_network)
+            .put_option(&self.asset_definition)
+            .put_option(&self.vesting);
+    }
+
     pub fn calculate_id(&self) -> String {
-        let mut hasher = Sha256::new();
-        let outputs_str = serde_json::to_string(&self.outputs).unwrap_or_default();
-        let payload_str = self.payload.clone().unwrap_or_default();
-        let data = format!("{}{}{}{}{}{:?}{:?}{}{}", self.version, self.nonce, self.sender, outputs_str, self.fee, self.source_network, self.target_network, self.timestamp, payload_str);
-        hasher.update(data);
-        format!("{:x}", hasher.finalize())
+        let mut enc = Encoder::new(tag::TX_ID);
+        self.encode_body(&mut enc);
+        enc.hash_hex()
+    }
+
+    /// Сообщение, которое подписывает отправитель: полное тело транзакции.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut enc = Encoder::new(tag::TX_SIGNATURE);
+        self.encode_body(&mut enc);
+        enc.into_bytes()
     }
     
+    /// Пересчитывает id (поля могли измениться после создания) и подписывает тело.
     pub fn sign(&mut self, keypair: &Keypair) {
-        let data = format!("{}{}", self.id, self.timestamp);
-        let signature = keypair.sign(data.as_bytes());
+        self.id = self.calculate_id();
+        let signature = keypair.sign(&self.signing_payload());
         self.signatures.push(signature.to_bytes().to_vec());
+        self.public_key = Some(keypair.public.to_bytes().to_vec());
+    }
+
+    /// Подпись сделана ключом, из которого выводится адрес отправителя.
+    pub fn verify_signer(&self) -> bool {
+        let public_key = match self.public_key.as_deref().map(ed25519_dalek::PublicKey::from_bytes) {
+            Some(Ok(public_key)) => public_key,
+            _ => return false,
+        };
+        generate_address(&public_key) == self.sender && self.verify(&public_key)
     }
     
     pub fn verify(&self, public_key: &ed25519_dalek::PublicKey) -> bool {
-        let data = format!("{}{}", self.id, self.timestamp);
+        if self.id != self.calculate_id() {
+            return false;
+        }
+        let message = self.signing_payload();
         self.signatures.iter().any(|sig_bytes| {
             if let Ok(signature) = Signature::from_bytes(sig_bytes) {
-                public_key.verify(data.as_bytes(), &signature).is_ok()
+                public_key.verify(&message, &signature).is_ok()
             } else {
                 false
             }
         })
     }
     
-    pub fn validate(&self) -> Result<(), String> {
-        if self.outputs.iter().map(|o| o.amount).sum::<u64>() + self.fee > 0 {
+    pub fn validate(&self, utxos: &UtxoSet) -> Result<(), String> {
+        // Актив можно зарегистрировать без начального выпуска
+        let declares_asset = matches!(self.tx_type, TxType::IssueAsset) && self.asset_definition.is_some();
+        if declares_asset || self.total_amount()? > 0 {
+            if let Some(schedule) = &self.vesting {
+                if !matches!(self.tx_type, TxType::Transfer) {
+                    return Err("Only transfers may carry a vesting schedule".into());
+                }
+                schedule.validate()?;
+            }
             if let TxType::ContractCall = self.tx_type {
                 if self.gas_limit.is_none() || self.gas_price.is_none() || self.payload.is_none() {
                     return Err("For ContractCall transactions, gas_limit, gas_price and payload must be set".into());
                 }
             }
+            if !self.inputs.is_empty() {
+                self.validate_inputs(utxos)?;
+            }
             Ok(())
         } else {
             Err("Invalid transaction: outputs and fee do not match".into())
         }
     }
-}
 
-pub fn calculate_merkle_root(transactions: &[Transaction]) -> String {
-    if transactions.is_empty() {
-        let mut hasher = Sha256::new();
-        hasher.update("");
-        return format!("{:x}", hasher.finalize());
-    }
-    let mut hashes: Vec<String> = transactions.iter().map(|tx| tx.id.clone()).collect();
-    while hashes.len() > 1 {
-        if hashes.len() % 2 != 0 {
-            if let Some(last) = hashes.last().cloned() {
-                hashes.push(last);
+    /// Входы должны существовать в UTXO-наборе, принадлежать отправителю
+    /// и по каждому активу покрывать выходы плюс комиссию (в нативном активе).
+    fn validate_inputs(&self, utxos: &UtxoSet) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        let mut available: HashMap<&str, u64> = HashMap::new();
+        for input in &self.inputs {
+            if !seen.insert(input) {
+                return Err(format!("Input {}:{} is spent twice", input.tx_id, input.output_index));
             }
+            let output = utxos.get(input)
+                .ok_or_else(|| format!("Input {}:{} is missing or already spent", input.tx_id, input.output_index))?;
+            if output.recipient != self.sender {
+                return Err(format!("Input {}:{} does not belong to {}", input.tx_id, input.output_index, self.sender));
+            }
+            add_amount(&mut available, &output.asset, output.amount)?;
+        }
+        let mut required: HashMap<&str, u64> = HashMap::new();
+        for output in &self.outputs {
+            add_amount(&mut required, &output.asset, output.amount)?;
         }
-        let mut new_level = Vec::with_capacity(hashes.len() / 2);
-        for i in (0..hashes.len()).step_by(2) {
-            let left = &hashes[i];
-            let right = &hashes[i+1];
-            let mut hasher = Sha256::new();
-            hasher.update(left);
-            hasher.update(right);
-            new_level.push(format!("{:x}", hasher.finalize()));
+        add_amount(&mut required, NATIVE_ASSET, self.fee)?;
+        for (asset, amount) in required {
+            let have = available.get(asset).copied().unwrap_or(0);
+            if have < amount {
+                return Err(format!("Inputs cover {} {}, but {} required", have, asset, amount));
+            }
         }
-        hashes = new_level;
+        Ok(())
     }
-    hashes[0].clone()
 }
+
+fn add_amount<'a>(totals: &mut HashMap<&'a str, u64>, asset: &'a str, amount: u64) -> Result<(), String> {
+    let total = totals.entry(asset).or_insert(0);
+    *total = total.checked_add(amount).ok_or_else(|| format!("Total of {} overflows u64", asset))?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     fn init_logger() {
         let _ = env_logger::builder().is_test(true).try_init();
     }
-    use ed25519_dalek::{Keypair, Verifier};
+    use ed25519_dalek::Keypair;
     use rand::rngs::OsRng;
 
     #[test]
     fn test_transaction_id() {
+        init_logger();
         let tx_output = TxOutput {
             asset: "TRD".to_string(),
             recipient: "recipient".to_string(),
@@ -166,6 +361,7 @@ mod tests {
 
     #[test]
     fn test_transaction_signature() {
+        init_logger();
         let mut rng = OsRng;
         let keypair = Keypair::generate(&mut rng);
         let tx_output = TxOutput {
@@ -176,5 +372,52 @@ mod tests {
         let mut tx = Transaction::new("sender".to_string(), vec![tx_output], 1);
         tx.sign(&keypair);
         assert!(tx.verify(&keypair.public), "Signature should be valid");
+        assert!(!tx.verify_signer(), "Signer key must match the sender address");
+
+        let mut own = Transaction::new(generate_address(&keypair.public), tx.outputs.clone(), 1);
+        own.sign(&keypair);
+        assert!(own.verify_signer());
+    }
+
+    #[test]
+    fn test_transaction_id_is_deterministic() {
+        let tx_output = TxOutput {
+            asset: "TRD".to_string(),
+            recipient: "recipient".to_string(),
+            amount: 50,
+        };
+        let tx = Transaction::new("sender".to_string(), vec![tx_output], 1);
+        assert_eq!(tx.id, tx.calculate_id());
+        let mut other = tx.clone();
+        other.sender = "sende".to_string();
+        other.outputs[0].recipient = "rrecipient".to_string();
+        assert_ne!(tx.id, other.calculate_id(), "Shifting bytes between fields must change the id");
+    }
+
+    #[test]
+    fn test_signature_covers_type_and_gas() {
+        let mut rng = OsRng;
+        let keypair = Keypair::generate(&mut rng);
+        let tx_output = TxOutput {
+            asset: "TRD".to_string(),
+            recipient: "recipient".to_string(),
+            amount: 50,
+        };
+        let mut tx = Transaction::new("sender".to_string(), vec![tx_output], 1);
+        tx.sign(&keypair);
+
+        let mut flipped = tx.clone();
+        flipped.tx_type = TxType::ContractCall;
+        assert!(!flipped.verify(&keypair.public), "Changing tx_type must invalidate the signature");
+
+        let mut regassed = tx.clone();
+        regassed.gas_price = Some(1_000);
+        regassed.id = regassed.calculate_id();
+        assert!(!regassed.verify(&keypair.public), "Changing gas must invalidate the signature");
+
+        let mut other_chain = tx.clone();
+        other_chain.chain_id = DEFAULT_CHAIN_ID + 1;
+        other_chain.id = other_chain.calculate_id();
+        assert!(!other_chain.verify(&keypair.public), "Signature must be bound to the chain id");
     }
 }
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::transaction::{Transaction, TxInput, TxOutput};
+
+/// Набор непотраченных выходов. Его ведёт `State` вместе с балансами
+/// аккаунтов: каждое зачисление создаёт выход, каждое списание тратит выходы
+/// владельца, поэтому сумма выходов адреса всегда равна его балансу.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct UtxoSet {
+    outputs: BTreeMap<String, TxOutput>,
+}
+
+fn outpoint_key(tx_id: &str, output_index: u32) -> String {
+    format!("{}:{}", tx_id, output_index)
+}
+
+impl UtxoSet {
+    pub fn get(&self, input: &TxInput) -> Option<&TxOutput> {
+        self.outputs.get(&outpoint_key(&input.tx_id, input.output_index))
+    }
+
+    pub fn contains(&self, input: &TxInput) -> bool {
+        self.get(input).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Сумма непотраченных выходов адреса по активу.
+    pub fn balance(&self, address: &str, asset: &str) -> u64 {
+        self.outputs.values()
+            .filter(|o| o.recipient == address && o.asset == asset)
+            .map(|o| o.amount)
+            .sum()
+    }
+
+    /// Создаёт выход `tx_id:index`. Существующий выход не перезаписывается:
+    /// повтор id транзакции уничтожил бы чужие монеты.
+    pub fn create(&mut self, tx_id: &str, index: u32, output: TxOutput) -> Result<(), String> {
+        let key = outpoint_key(tx_id, index);
+        if self.outputs.contains_key(&key) {
+            return Err(format!("Output {} already exists", key));
+        }
+        self.outputs.insert(key, output);
+        Ok(())
+    }
+
+    /// Тратит явно указанные входы транзакции и возвращает их суммы по активам.
+    /// Существование, владельца и покрытие заранее проверяет `Transaction::validate`.
+    pub fn spend_inputs(&mut self, tx: &Transaction) -> Result<BTreeMap<String, u64>, String> {
+        let mut spent = BTreeMap::new();
+        for input in &tx.inputs {
+            let output = self.outputs.remove(&outpoint_key(&input.tx_id, input.output_index))
+                .ok_or_else(|| format!("Input {}:{} is missing or already spent", input.tx_id, input.output_index))?;
+            let total: &mut u64 = spent.entry(output.asset).or_default();
+            *total = total.checked_add(output.amount).ok_or("Spent inputs overflow u64")?;
+        }
+        Ok(spent)
+    }
+
+    /// Списание без явных входов: тратит выходы владельца в порядке ключей,
+    /// пока не наберётся `amount`, и возвращает набранную сумму (сдачу
+    /// вызывающий зачисляет отдельным выходом).
+    pub fn spend_from(&mut self, owner: &str, asset: &str, amount: u64) -> Result<u64, String> {
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for (key, output) in &self.outputs {
+            if total >= amount {
+                break;
+            }
+            if output.recipient == owner && output.asset == asset {
+                selected.push(key.clone());
+                total = total.saturating_add(output.amount);
+            }
+        }
+        if total < amount {
+            return Err(format!("Outputs of {} cover {} {}, but {} required", owner, total, asset, amount));
+        }
+        for key in selected {
+            self.outputs.remove(&key);
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(recipient: &str, amount: u64) -> TxOutput {
+        TxOutput { asset: "TRD".into(), recipient: recipient.into(), amount }
+    }
+
+    #[test]
+    fn test_spend_and_double_spend() {
+        let mut utxos = UtxoSet::default();
+        let funding = Transaction::new("genesis".into(), vec![output("alice", 60), output("alice", 40)], 0);
+        utxos.create(&funding.id, 0, funding.outputs[0].clone()).unwrap();
+        utxos.create(&funding.id, 1, funding.outputs[1].clone()).unwrap();
+        assert_eq!(utxos.balance("alice", "TRD"), 100);
+        assert!(utxos.create(&funding.id, 0, output("mallory", 1)).is_err(), "Existing outputs must not be overwritten");
+
+        let input = TxInput { tx_id: funding.id.clone(), output_index: 0 };
+        let spend = Transaction::new_with_inputs("alice".into(), vec![input.clone()], vec![output("bob", 50)], 10);
+        assert_eq!(utxos.spend_inputs(&spend).unwrap().get("TRD"), Some(&60));
+        assert!(!utxos.contains(&input));
+        assert!(utxos.spend_inputs(&spend).is_err(), "Spent output must not be spendable again");
+
+        assert!(utxos.spend_from("alice", "TRD", 41).is_err());
+        assert_eq!(utxos.spend_from("alice", "TRD", 30).unwrap(), 40, "Whole outputs are spent, the rest is change");
+        assert!(utxos.is_empty());
+    }
+
+    #[test]
+    fn test_inputs_must_cover_outputs_and_fee() {
+        let mut utxos = UtxoSet::default();
+        let funding = Transaction::new("genesis".into(), vec![output("alice", 100)], 0);
+        utxos.create(&funding.id, 0, funding.outputs[0].clone()).unwrap();
+        let input = TxInput { tx_id: funding.id.clone(), output_index: 0 };
+        let overspend = Transaction::new_with_inputs("alice".into(), vec![input], vec![output("bob", 95)], 10);
+        assert!(overspend.validate(&utxos).is_err());
+
+        let overflow = Transaction::new("alice".into(), vec![output("bob", u64::MAX)], 1);
+        assert!(overflow.validate(&utxos).is_err(), "Overflowing totals must be rejected, not panic");
+    }
+}
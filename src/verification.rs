@@ -0,0 +1,192 @@
+use std::fmt;
+use rayon::prelude::*;
+use crate::block::{Block, BlockHeader};
+use crate::consensus_plugin::{ChainContext, PluginManager};
+use crate::state::State;
+
+/// Почему блок не прошёл проверку цепочки.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    /// Высота не совпадает с позицией блока в цепочке.
+    Height { expected: u64 },
+    /// `parent_hash` не указывает на предыдущий блок.
+    ParentHash { expected: String },
+    /// Сохранённый хеш не совпадает с пересчитанным.
+    BlockHash,
+    MerkleRoot,
+    /// `id` транзакции не совпадает с хешем её тела: листья Меркла покрывают
+    /// только id, поэтому иначе содержимое не было бы связано с блоком.
+    TransactionId { tx_id: String },
+    /// Подпись отсутствует, неверна или ключ не соответствует производителю.
+    BlockSignature,
+    TransactionSignature { tx_id: String },
+    /// Блок отклонён плагином консенсуса.
+    Consensus { plugin: &'static str },
+    /// Блок не применяется к состоянию (nonce, балансы, корень состояния).
+    StateTransition(String),
+}
+
+/// Первый блок цепочки, не прошедший проверку.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    pub height: u64,
+    pub hash: String,
+    pub kind: VerifyErrorKind,
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::Height { expected } => write!(f, "unexpected height, expected {}", expected),
+            VerifyErrorKind::ParentHash { expected } => write!(f, "parent hash does not match previous block {}", expected),
+            VerifyErrorKind::BlockHash => write!(f, "stored hash does not match block contents"),
+            VerifyErrorKind::MerkleRoot => write!(f, "merkle root does not match transactions"),
+            VerifyErrorKind::TransactionId { tx_id } => write!(f, "transaction id {} does not match its contents", tx_id),
+            VerifyErrorKind::BlockSignature => write!(f, "invalid producer signature"),
+            VerifyErrorKind::TransactionSignature { tx_id } => write!(f, "invalid signature on transaction {}", tx_id),
+            VerifyErrorKind::Consensus { plugin } => write!(f, "rejected by consensus plugin {}", plugin),
+            VerifyErrorKind::StateTransition(e) => write!(f, "state transition failed: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Block {} ({}): {}", self.height, self.hash, self.kind)
+    }
+}
+
+/// Проверки, которым нужен только сам блок и заголовки его предков: высота,
+/// хеш, связь с родителем, корень Меркла, подписи и плагины консенсуса.
+/// Их проходит и каждый блок `put_block`, и каждый блок `verify_chain`.
+pub fn check_block(block: &Block, ancestors: &[BlockHeader], plugins: &PluginManager) -> Result<(), VerifyErrorKind> {
+    let height = ancestors.len() as u64;
+    if block.header.height != height {
+        return Err(VerifyErrorKind::Height { expected: height });
+    }
+    if !block.verify_hash() {
+        return Err(VerifyErrorKind::BlockHash);
+    }
+    if let Some(parent) = ancestors.last() {
+        let expected = parent.hash();
+        if block.header.parent_hash != expected {
+            return Err(VerifyErrorKind::ParentHash { expected });
+        }
+    }
+    if !block.verify_merkle_root() {
+        return Err(VerifyErrorKind::MerkleRoot);
+    }
+    if let Some(tx) = block.body.transactions.iter().find(|tx| tx.id != tx.calculate_id()) {
+        return Err(VerifyErrorKind::TransactionId { tx_id: tx.id.clone() });
+    }
+    // Генезис задаётся конфигурацией сети: его никто не подписывает и не майнит
+    if height == 0 {
+        return Ok(());
+    }
+    if !block.verify_producer() {
+        return Err(VerifyErrorKind::BlockSignature);
+    }
+    if let Some(tx) = block.body.transactions.iter().find(|tx| !tx.is_coinbase() && !tx.verify_signer()) {
+        return Err(VerifyErrorKind::TransactionSignature { tx_id: tx.id.clone() });
+    }
+    if let Some(plugin) = plugins.rejecting_plugin(block, &ChainContext { ancestors }) {
+        return Err(VerifyErrorKind::Consensus { plugin });
+    }
+    Ok(())
+}
+
+/// Проверяет цепочку от генезиса: связность по `parent_hash`, пересчитанные
+/// хеши, корни Меркла, подписи блоков и транзакций, правила консенсуса и
+/// переходы состояния. Независимые проверки блоков идут параллельно, переходы
+/// состояния — последовательно, начиная с пустого состояния сети `initial`;
+/// возвращается ошибка блока с наименьшей высотой.
+pub fn verify_chain(blocks: &[Block], initial: State, plugins: &PluginManager) -> Result<(), VerifyError> {
+    let headers = Block::headers(blocks);
+    let failure = (0..blocks.len()).into_par_iter()
+        .find_map_first(|i| check_block(&blocks[i], &headers[..i], plugins).err().map(|kind| (i, kind)));
+
+    let checked = failure.as_ref().map_or(blocks.len(), |(i, _)| *i);
+    let mut state = initial;
+    let transition = blocks[..checked].iter().enumerate()
+        .find_map(|(i, block)| state.apply_block(block).err().map(|e| (i, VerifyErrorKind::StateTransition(e))));
+
+    match transition.or(failure) {
+        Some((i, kind)) => Err(VerifyError { height: i as u64, hash: blocks[i].calculate_hash(), kind }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::PoW;
+    use crate::difficulty::next_bits;
+    use crate::miner::{CancellationToken, MineResult, Miner};
+    use crate::transaction::{Transaction, TxOutput, DEFAULT_CHAIN_ID};
+    use crate::wallet::generate_address;
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    fn build_chain(keypair: &Keypair, length: u64) -> Vec<Block> {
+        let address = generate_address(&keypair.public);
+        let output = TxOutput { asset: "TRD".into(), recipient: address.clone(), amount: 100 };
+        let mut genesis = Block::new(0, "0".into(), 1675303065, vec![Transaction::new("genesis".into(), vec![output], 0)], "Hybrid".into());
+        genesis.header.state_root = State::default().state_root_after(&genesis).unwrap();
+        genesis.hash = Some(genesis.calculate_hash());
+
+        let mut blocks = vec![genesis];
+        let mut state = State::from_blocks(State::default(), &blocks).unwrap();
+        for height in 1..length {
+            let output = TxOutput { asset: "TRD".into(), recipient: "bob".into(), amount: 1 };
+            let mut tx = Transaction::new(address.clone(), vec![output], 0);
+            tx.nonce = height - 1;
+            tx.sign(keypair);
+            let parent = blocks.last().unwrap();
+            let mut block = Block::new(height, parent.calculate_hash(), parent.header.timestamp + 60, vec![tx], "Hybrid".into());
+            block.header.producer = address.clone();
+            block.insert_coinbase(DEFAULT_CHAIN_ID).unwrap();
+            block.header.bits = next_bits(&Block::headers(&blocks));
+            block.header.state_root = state.state_root_after(&block).unwrap();
+            block.sign(keypair);
+            let block = match Miner::default().mine(block, &CancellationToken::new(), |_| {}) {
+                MineResult::Found { block, .. } => *block,
+                _ => panic!("Block should be mined"),
+            };
+            state.apply_block(&block).unwrap();
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    fn plugins() -> PluginManager {
+        let mut plugins = PluginManager::new();
+        plugins.register_plugin(Box::new(PoW::default()));
+        plugins
+    }
+
+    #[test]
+    fn test_reports_first_failing_block() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let blocks = build_chain(&keypair, 4);
+        assert_eq!(verify_chain(&blocks, State::default(), &plugins()), Ok(()));
+
+        let mut forged = blocks.clone();
+        forged[2].body.transactions[1].signatures[0][0] ^= 1;
+        let tx_id = forged[2].body.transactions[1].id.clone();
+        let error = verify_chain(&forged, State::default(), &plugins()).unwrap_err();
+        assert_eq!((error.height, error.kind), (2, VerifyErrorKind::TransactionSignature { tx_id }));
+
+        let mut rebound = blocks.clone();
+        rebound[3].body.transactions[0].outputs[0].recipient = "someone".into();
+        let tx_id = rebound[3].body.transactions[0].id.clone();
+        let error = verify_chain(&rebound, State::default(), &plugins()).unwrap_err();
+        assert_eq!((error.height, error.kind), (3, VerifyErrorKind::TransactionId { tx_id }), "Coinbase outputs are bound to its id");
+
+        let mut relinked = blocks.clone();
+        relinked[3].header.parent_hash = "0".into();
+        relinked[1].header.producer = "someone".into();
+        relinked[1].hash = Some(relinked[1].calculate_hash());
+        let error = verify_chain(&relinked, State::default(), &plugins()).unwrap_err();
+        assert_eq!(error.height, 1, "The lowest failing block must be reported");
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Serialize, Deserialize};
+use crate::encoding::{Encode, Encoder};
+
+/// График вестинга по высотам блоков: до `cliff_height` заблокирована вся
+/// сумма, затем она линейно разблокируется от `start_height` до `end_height`
+/// (на высоте cliff сразу становится доступна уже «накопленная» часть).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VestingSchedule {
+    pub start_height: u64,
+    pub cliff_height: u64,
+    pub end_height: u64,
+}
+
+impl Encode for VestingSchedule {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u64(self.start_height).put_u64(self.cliff_height).put_u64(self.end_height);
+    }
+}
+
+impl VestingSchedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.start_height > self.cliff_height || self.cliff_height > self.end_height || self.start_height == self.end_height {
+            return Err(format!("Invalid vesting schedule {:?}: expected start <= cliff <= end and start < end", self));
+        }
+        Ok(())
+    }
+
+    /// Сколько из `amount` разблокировано на высоте `height`.
+    pub fn vested(&self, amount: u64, height: u64) -> u64 {
+        if height < self.cliff_height {
+            return 0;
+        }
+        if height >= self.end_height {
+            return amount;
+        }
+        let elapsed = height.saturating_sub(self.start_height) as u128;
+        (amount as u128 * elapsed / (self.end_height - self.start_height) as u128) as u64
+    }
+}
+
+/// Заблокированная часть баланса аккаунта. Токены лежат в кошельке, но
+/// тратить можно только то, что превышает ещё не разблокированную сумму.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VestingLock {
+    pub asset: String,
+    pub amount: u64,
+    pub schedule: VestingSchedule,
+}
+
+impl Encode for VestingLock {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_str(&self.asset).put_u64(self.amount).put(&self.schedule);
+    }
+}
+
+impl VestingLock {
+    pub fn locked(&self, height: u64) -> u64 {
+        self.amount - self.schedule.vested(self.amount, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cliff_and_linear_unlock() {
+        let lock = VestingLock {
+            asset: "TRD".into(),
+            amount: 1_000,
+            schedule: VestingSchedule { start_height: 0, cliff_height: 25, end_height: 100 },
+        };
+        let locked: Vec<u64> = [0, 24, 25, 50, 99, 100, 1_000].iter().map(|&h| lock.locked(h)).collect();
+        assert_eq!(locked, vec![1_000, 1_000, 750, 500, 10, 0, 0]);
+        assert!(VestingSchedule { start_height: 10, cliff_height: 5, end_height: 20 }.validate().is_err());
+        let unchecked = VestingSchedule { start_height: 10, cliff_height: 5, end_height: 20 };
+        assert_eq!(unchecked.vested(1_000, 6), 0, "A cliff before the start must not underflow");
+    }
+}
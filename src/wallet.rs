@@ -1,7 +1,7 @@
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use sha2::{Sha256, Digest};
-use ed25519_dalek::PublicKey;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
 
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub struct TokenWallet {
@@ -11,9 +11,7 @@ pub struct TokenWallet {
 
 impl TokenWallet {
     pub fn new(address: String) -> Self {
-        let mut balances = HashMap::new();
-        balances.insert("TRD".into(), 1000);
-        TokenWallet { address, balances }
+        TokenWallet { address, balances: HashMap::new() }
     }
     
     pub fn add_tokens(&mut self, asset: &str, amount: u64) {
@@ -41,3 +39,16 @@ pub fn generate_address(public_key: &PublicKey) -> String {
     hasher.update(public_key.as_bytes());
     format!("{:x}", hasher.finalize())
 }
+
+/// Адрес в формате `generate_address`: hex SHA-256 открытого ключа.
+pub fn is_valid_address(address: &str) -> bool {
+    address.len() == 64 && address.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Детерминированная пара ключей из произвольной строки-зерна (SHA-256 от неё —
+/// секретный ключ). Только для dev-сетей и тестов: зерно равносильно ключу.
+pub fn keypair_from_seed(seed: &str) -> Keypair {
+    let secret = SecretKey::from_bytes(&Sha256::digest(seed.as_bytes())).expect("32-byte secret key");
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}